@@ -0,0 +1,414 @@
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    env::var,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::config::ConfigIssue;
+
+/// Every key of the configuration and the environment variable overriding it
+pub const KEYS: [(&str, &str); 41] = [
+    ("server.listen", "LISTEN"),
+    ("server.host", "HOST"),
+    ("server.port", "PORT"),
+    ("server.tls.listen", "TLS_LISTEN"),
+    ("server.tls.cert", "TLS_CERT"),
+    ("server.tls.key", "TLS_KEY"),
+    ("server.tls.redirect", "TLS_REDIRECT"),
+    ("server.tls.reload_interval", "TLS_RELOAD_INTERVAL"),
+    ("mongo.url", "MONGO_URL"),
+    ("mongo.db_name", "DB_NAME"),
+    ("redis.url", "REDIS_URL"),
+    ("email.from", "EMAIL_FROM"),
+    ("email.reply_to", "EMAIL_REPLY_TO"),
+    ("email.transport", "EMAIL_TRANSPORT"),
+    ("email.smtp.host", "EMAIL_HOST"),
+    ("email.smtp.port", "EMAIL_PORT"),
+    ("email.smtp.username", "EMAIL_USERNAME"),
+    ("email.smtp.password", "EMAIL_PASSWORD"),
+    ("email.file_dir", "EMAIL_FILE_DIR"),
+    ("email.template_dir", "EMAIL_TEMPLATE_DIR"),
+    ("email.template_reload", "EMAIL_TEMPLATE_RELOAD"),
+    ("code.expire", "CODE_EXPIRE"),
+    ("code.max_attempts", "CODE_MAX_ATTEMPTS"),
+    ("code.secret", "CODE_SECRET"),
+    ("rate_limit.code.per_ip", "RATE_LIMIT_CODE_PER_IP"),
+    ("rate_limit.code.per_email", "RATE_LIMIT_CODE_PER_EMAIL"),
+    ("rate_limit.code.window", "RATE_LIMIT_CODE_WINDOW"),
+    ("rate_limit.auth.per_ip", "RATE_LIMIT_AUTH_PER_IP"),
+    ("rate_limit.auth.per_email", "RATE_LIMIT_AUTH_PER_EMAIL"),
+    ("rate_limit.auth.window", "RATE_LIMIT_AUTH_WINDOW"),
+    ("rate_limit.trust_proxy", "RATE_LIMIT_TRUST_PROXY"),
+    ("migrate_on_startup", "MIGRATE_ON_STARTUP"),
+    ("outbox.max_attempts", "OUTBOX_MAX_ATTEMPTS"),
+    ("outbox.backoff", "OUTBOX_BACKOFF"),
+    ("outbox.max_backoff", "OUTBOX_MAX_BACKOFF"),
+    ("outbox.poll_interval", "OUTBOX_POLL_INTERVAL"),
+    ("health.check_smtp", "HEALTH_CHECK_SMTP"),
+    ("health.timeout", "HEALTH_TIMEOUT"),
+    ("health.shutdown_delay", "SHUTDOWN_DELAY"),
+    ("metrics.listen", "METRICS_LISTEN"),
+    ("metrics.public", "METRICS_PUBLIC"),
+];
+
+/// Set of files read by `Source::load`, selected by the `APP_PROFILE` environment variable,
+/// `dev` by default in debug builds and `prod` in release builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Dev,
+    Test,
+    Prod,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            Profile::Dev
+        } else {
+            Profile::Prod
+        }
+    }
+}
+
+impl Profile {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Test => "test",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(profile: &str) -> Result<Self, Self::Err> {
+        match profile.to_lowercase().as_str() {
+            "dev" | "development" => Ok(Profile::Dev),
+            "test" => Ok(Profile::Test),
+            "prod" | "production" => Ok(Profile::Prod),
+            _ => Err("expected one of dev, test, prod".to_owned()),
+        }
+    }
+}
+
+/// Where the value of a key comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Env(&'static str),
+    /// Set through `Source::set`
+    Set,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "file {}", path.display()),
+            Origin::Env(name) => write!(f, "env {}", name),
+            Origin::Set => write!(f, "set"),
+        }
+    }
+}
+
+/// Raw values of the configuration, by key, layered from lowest to highest precedence:
+/// `{CONFIG_DIR}/default.{toml,yaml,yml}`, `{CONFIG_DIR}/{profile}.{toml,yaml,yml}`,
+/// `CONFIG_FILE`, then the environment variables
+#[derive(Debug, Clone, Default)]
+pub struct Source {
+    profile: Profile,
+    values: HashMap<String, (String, Origin)>,
+    pub(super) issues: Vec<ConfigIssue>,
+}
+
+impl Source {
+    /// Empty source without the environment, the values are given through `set` or `with_file`
+    pub fn new(profile: Profile) -> Self {
+        Self {
+            profile,
+            ..Self::default()
+        }
+    }
+
+    /// Files of the profile and the environment variables
+    pub fn load() -> Self {
+        Self::load_with(|name| var(name).ok(), |path| fs::read_to_string(path))
+    }
+
+    /// `load` with the given environment variables and files
+    fn load_with(
+        var: impl Fn(&str) -> Option<String>,
+        read: impl Fn(&Path) -> io::Result<String>,
+    ) -> Self {
+        let mut source = Self::default();
+
+        if let Some(profile) = var("APP_PROFILE") {
+            match profile.parse::<Profile>() {
+                Ok(profile) => source.profile = profile,
+                Err(message) => source.issue("APP_PROFILE".to_owned(), message),
+            }
+        }
+
+        let dir = var("CONFIG_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("config"));
+
+        for name in ["default", source.profile.name()] {
+            for extension in ["toml", "yaml", "yml"] {
+                let path = dir.join(format!("{}.{}", name, extension));
+
+                match read(&path) {
+                    // the files of the profile are optional
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    content => source.read_content(&path, content),
+                }
+            }
+        }
+
+        // unlike the files of the profile, an explicit file must exist
+        if let Some(file) = var("CONFIG_FILE") {
+            let path = Path::new(&file);
+
+            source.read_content(path, read(path));
+        }
+
+        // empty variables are ignored, like unset ones
+        for (key, name) in KEYS {
+            if let Some(value) = var(name).filter(|value| !value.is_empty()) {
+                source.insert(key.to_owned(), value, Origin::Env(name));
+            }
+        }
+
+        source
+    }
+
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+
+        self.read_content(path, fs::read_to_string(path));
+
+        self
+    }
+
+    pub fn set(mut self, key: &str, value: impl ToString) -> Self {
+        self.insert(key.to_owned(), value.to_string(), Origin::Set);
+
+        self
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Value of the key from the layer with the highest precedence, empty values are ignored
+    pub(super) fn get(&self, key: &str) -> Option<(String, Origin)> {
+        self.values
+            .get(key)
+            .filter(|(value, _)| !value.is_empty())
+            .cloned()
+    }
+
+    /// Values of a file over the ones read before
+    fn read_content(&mut self, path: &Path, content: io::Result<String>) {
+        let at = path.display().to_string();
+
+        let content = match content {
+            Ok(content) => content,
+            Err(e) => return self.issue(at, format!("cannot be read: {}", e)),
+        };
+
+        let parsed = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str::<Value>(&content).map_err(|e| e.to_string()),
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str::<Value>(&content).map_err(|e| e.to_string())
+            }
+            _ => Err("expected a .toml, .yaml or .yml file".to_owned()),
+        };
+
+        match parsed {
+            Ok(value) => {
+                let mut values = Vec::new();
+
+                flatten(String::new(), value, &mut values);
+
+                for (key, value) in values {
+                    self.insert(key, value, Origin::File(path.to_owned()));
+                }
+            }
+            Err(message) => self.issue(at, message),
+        }
+    }
+
+    fn insert(&mut self, key: String, value: String, origin: Origin) {
+        if env_name(&key).is_none() {
+            return self.issue(
+                format!("{} ({})", key, origin),
+                "is not a known key".to_owned(),
+            );
+        }
+
+        self.values.insert(key, (value, origin));
+    }
+
+    fn issue(&mut self, at: String, message: String) {
+        self.issues.push(ConfigIssue { at, message });
+    }
+}
+
+pub(super) fn env_name(key: &str) -> Option<&'static str> {
+    KEYS.iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, env)| *env)
+}
+
+/// Nested tables become dotted keys, arrays are joined with commas like in the environment
+fn flatten(prefix: String, value: Value, values: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+
+                flatten(key, value, values);
+            }
+        }
+        Value::Array(items) => {
+            let items = items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(item) => item,
+                    item => item.to_string(),
+                })
+                .collect::<Vec<_>>();
+
+            values.push((prefix, items.join(",")));
+        }
+        Value::String(value) => values.push((prefix, value)),
+        Value::Null => {}
+        value => values.push((prefix, value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io, path::Path};
+
+    use super::{Origin, Profile, Source};
+
+    /// `Source::load` with the given environment variables and files
+    fn load(vars: &[(&str, &str)], files: &[(&str, &str)]) -> Source {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        let files = files
+            .iter()
+            .map(|(path, content)| (Path::new(path).to_owned(), content.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        Source::load_with(
+            |name| vars.get(name).cloned(),
+            |path| {
+                files
+                    .get(path)
+                    .cloned()
+                    .ok_or_else(|| io::ErrorKind::NotFound.into())
+            },
+        )
+    }
+
+    fn value(source: &Source, key: &str) -> Option<String> {
+        source.get(key).map(|(value, _)| value)
+    }
+
+    #[test]
+    fn layers_override_the_lower_ones() {
+        let source = load(
+            &[
+                ("APP_PROFILE", "test"),
+                ("CONFIG_DIR", "/etc/app"),
+                ("CONFIG_FILE", "/run/app.yaml"),
+                ("PORT", "4"),
+            ],
+            &[
+                (
+                    "/etc/app/default.toml",
+                    "server.port = 1\nmongo.db_name = \"default\"\n\
+                     redis.url = \"redis://default\"\ncode.secret = \"default\"",
+                ),
+                (
+                    "/etc/app/test.toml",
+                    "[server]\nport = 2\n[mongo]\ndb_name = \"test\"\n\
+                     [redis]\nurl = \"redis://test\"",
+                ),
+                (
+                    "/run/app.yaml",
+                    "server:\n  port: 3\nmongo:\n  db_name: file",
+                ),
+            ],
+        );
+
+        assert_eq!(source.profile(), Profile::Test);
+        assert!(source.issues.is_empty());
+
+        assert_eq!(
+            source.get("server.port"),
+            Some(("4".to_owned(), Origin::Env("PORT")))
+        );
+        assert_eq!(value(&source, "mongo.db_name"), Some("file".to_owned()));
+        assert_eq!(value(&source, "redis.url"), Some("redis://test".to_owned()));
+        assert_eq!(value(&source, "code.secret"), Some("default".to_owned()));
+        assert_eq!(
+            source.get("code.secret").map(|(_, origin)| origin),
+            Some(Origin::File(Path::new("/etc/app/default.toml").to_owned()))
+        );
+    }
+
+    #[test]
+    fn empty_variables_are_ignored() {
+        let source = load(
+            &[("PORT", "")],
+            &[("config/default.yml", "server:\n  port: 1")],
+        );
+
+        assert_eq!(value(&source, "server.port"), Some("1".to_owned()));
+    }
+
+    #[test]
+    fn report_unknown_keys_bad_files_and_profiles() {
+        let source = load(
+            &[("APP_PROFILE", "staging"), ("CONFIG_FILE", "missing.toml")],
+            &[("config/default.toml", "server.prot = 1\nmongo.url = [")],
+        );
+
+        let issues = source
+            .issues
+            .iter()
+            .map(|issue| issue.at.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            issues,
+            vec!["APP_PROFILE", "config/default.toml", "missing.toml"]
+        );
+    }
+
+    #[test]
+    fn report_unknown_keys_of_valid_files() {
+        let source = load(&[], &[("config/default.toml", "server.prot = 1")]);
+
+        assert_eq!(source.issues.len(), 1);
+        assert!(source.issues[0].at.starts_with("server.prot"));
+        assert_eq!(source.issues[0].message, "is not a known key");
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::{rt::time::timeout, web::Data, HttpResponse};
+use futures::future::{join3, OptionFuture};
+use serde_json::{json, Map, Value};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+use crate::{controllers::Response, database::redis::Redis, errors::Error, state::State};
+
+/// Liveness, the process is able to answer, the dependencies are not checked
+pub async fn liveness() -> Response {
+    Ok(HttpResponse::Ok().json(json!({ "status": "ok" })))
+}
+
+/// Readiness, mongodb when the repositories use it, redis and optionally the SMTP server are reachable,
+/// `503 Service Unavailable` once the server is shutting down
+pub async fn readiness(state: Data<State>, redis: Data<Redis>) -> Response {
+    if state.shutdown.is_started() {
+        return Ok(HttpResponse::ServiceUnavailable().json(json!({ "status": "shutting_down" })));
+    }
+
+    let health_config = &state.config.health_config;
+    let limit = Duration::from_secs(health_config.timeout);
+
+    let smtp: OptionFuture<_> = health_config
+        .check_smtp
+        .then(|| check("smtp", limit, state.email.test_connection()))
+        .into();
+
+    let mongo: OptionFuture<_> = state
+        .database
+        .as_ref()
+        .map(|database| check("mongo", limit, database.ping()))
+        .into();
+
+    let (mongo, redis, smtp) = join3(mongo, check("redis", limit, redis.ping()), smtp).await;
+
+    let mut checks = Map::new();
+
+    if let Some(mongo) = mongo {
+        checks.insert("mongo".to_owned(), mongo);
+    }
+
+    checks.insert("redis".to_owned(), redis);
+
+    if let Some(smtp) = smtp {
+        checks.insert("smtp".to_owned(), smtp);
+    }
+
+    let ready = checks.values().all(|check| check["status"] == "up");
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": checks,
+    });
+
+    if ready {
+        Ok(HttpResponse::Ok().json(body))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(body))
+    }
+}
+
+/// Status and duration of one check, the errors are only logged,
+/// they may contain addresses of the infrastructure
+async fn check(
+    name: &str,
+    limit: Duration,
+    future: impl Future<Output = Result<(), Error>>,
+) -> Value {
+    let started = Instant::now();
+    let result = timeout(limit, future).await;
+    let duration = started.elapsed().as_millis() as u64;
+
+    let status = match result {
+        Ok(Ok(())) => "up",
+        Ok(Err(e)) => {
+            warn!("Health check of {} failed: {}", name, e);
+            "down"
+        }
+        Err(_) => {
+            warn!("Health check of {} timed out after {}ms", name, duration);
+            "timeout"
+        }
+    };
+
+    json!({ "status": status, "durationMs": duration })
+}
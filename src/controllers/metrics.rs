@@ -0,0 +1,10 @@
+use actix_web::HttpResponse;
+
+use crate::{controllers::Response, metrics};
+
+/// Every metric in the Prometheus text format
+pub async fn scrape() -> Response {
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics::render()))
+}
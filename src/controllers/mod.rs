@@ -2,6 +2,8 @@ use actix_web::HttpResponse;
 
 use crate::errors::Error;
 
+pub mod health;
+pub mod metrics;
 pub mod users;
 
 pub type Response = Result<HttpResponse, Error>;
@@ -0,0 +1,125 @@
+use actix_identity::Identity;
+use actix_session::Session;
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+use mongodb::bson::oid::ObjectId;
+use serde_json::json;
+use serde_qs::actix::QsQuery;
+use validator::Validate;
+
+use crate::{
+    controllers::Response,
+    errors::Error::{self, BadRequest, Forbidden, NotFound},
+    extractors::users::AuthenticatedUser,
+    i18n::{t, t_args},
+    models::{
+        users::{query::UserQuery, role::Role, updater::UserUpdater, User},
+        vec_into_json, IntoJson,
+    },
+    state::State,
+    utils::session,
+};
+
+pub async fn list_users(query: QsQuery<UserQuery>, state: Data<State>) -> Response {
+    query.validate()?;
+
+    let (users, total, next_cursor) = state.users.paginate(&query).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "users": vec_into_json(users),
+        "total": total,
+        "nextCursor": next_cursor,
+    })))
+}
+
+pub async fn get_user(
+    id: Path<String>,
+    AuthenticatedUser(actor): AuthenticatedUser,
+    state: Data<State>,
+) -> Response {
+    let user = find_manageable(id.into_inner(), &actor, &state).await?;
+
+    let value = user.into_json();
+
+    Ok(HttpResponse::Ok().json(json!({ "user": value })))
+}
+
+pub async fn update_user(
+    id: Path<String>,
+    Json(updater): Json<UserUpdater>,
+    AuthenticatedUser(actor): AuthenticatedUser,
+    state: Data<State>,
+    session: Session,
+) -> Response {
+    updater.validate()?;
+
+    let user = find_manageable(id.into_inner(), &actor, &state).await?;
+
+    let username = updater.username;
+    let locale = updater.locale;
+
+    if username.is_none() && locale.is_none() {
+        return Err(BadRequest(t("user.nothing_to_update")));
+    }
+
+    let user = state
+        .users
+        .update_profile(user.id, username, None, locale)
+        .await?;
+
+    // the messages of the current session follow the new locale
+    if let (Some(locale), true) = (locale, user.id == actor.id) {
+        session::set_locale(&session, locale)?;
+    }
+
+    let value = user.into_json();
+
+    Ok(HttpResponse::Ok().json(json!({ "user": value })))
+}
+
+pub async fn delete_user(
+    id: Path<String>,
+    AuthenticatedUser(actor): AuthenticatedUser,
+    identity: Identity,
+    state: Data<State>,
+) -> Response {
+    let user = find_manageable(id.into_inner(), &actor, &state).await?;
+
+    state.users.delete(user.id).await?;
+
+    // deleting yourself ends the current session as well
+    if user.id == actor.id {
+        identity.logout();
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Find the user with the given id, if the actor is allowed to manage it
+async fn find_manageable(id: String, actor: &User, state: &State) -> Result<User, Error> {
+    let not_found = NotFound(t_args("user.id_not_found", &[("id", &id)]));
+    let forbidden = Forbidden(t("user.cannot_manage"));
+
+    let id = match ObjectId::parse_str(&id) {
+        Ok(id) => id,
+        Err(_) => return Err(not_found),
+    };
+
+    // only admins may look up other users
+    if actor.id != id && !actor.has_role(&Role::Admin) {
+        return Err(forbidden);
+    }
+
+    let user = match state.users.find_one_by_id(id).await? {
+        Some(user) => user,
+        None => return Err(not_found),
+    };
+
+    if !actor.can_manage(&user) {
+        return Err(forbidden);
+    }
+
+    Ok(user)
+}
@@ -1,23 +1,25 @@
 use actix_identity::Identity;
 use actix_web::{
     web::{Data, Json},
-    HttpMessage, HttpRequest, HttpResponse,
+    HttpRequest, HttpResponse,
 };
 use serde_json::json;
 use validator::Validate;
 
 use crate::{
     controllers::Response,
-    errors::Error::BadRequest,
+    errors::Error::{BadRequest, Unauthorized},
+    i18n::t,
     models::{
         users::{
-            auth::Registrar,
+            auth::{Credentials, PasswordResetter, Registrar},
             codes::{Code, CodeType},
             User,
         },
         IntoJson,
     },
     state::State,
+    utils::session,
 };
 
 pub async fn register(
@@ -30,33 +32,104 @@ pub async fn register(
     let email = registrar.email.to_owned();
     let candidate = registrar.code.to_owned();
 
-    let invalid_code =
-        BadRequest("Invalid registration code, please check your email and try again".to_owned());
+    let invalid_code = BadRequest(t("code.invalid_registration"));
 
-    // check if the code is valid
-    match Code::find_one_by_email(email, CodeType::Registration, &state.database).await? {
-        Some(code) => {
-            // if code is not expired, return error
-            if !code.is_valid(candidate) {
-                return Err(invalid_code);
-            }
-
-            // if code is valid, deactivate it
-            code.deactivate_by_id(&state.database).await?;
-        }
-        None => {
-            // if no code found, return error
-            return Err(invalid_code);
-        }
+    // check if the code is valid, and deactivate it if so
+    if !Code::consume(
+        email,
+        CodeType::Registration,
+        None,
+        candidate,
+        &state.config,
+        state.codes.as_ref(),
+    )
+    .await?
+    {
+        return Err(invalid_code);
     }
 
     let new_user = registrar.build();
 
-    User::create(&new_user, &state.database).await?;
+    state.users.create(&new_user).await?;
 
-    Identity::login(&request.extensions(), new_user.id.to_hex())?;
+    session::login(&request, &new_user)?;
 
     let value = new_user.into_json();
 
     Ok(HttpResponse::Created().json(json!({ "user": value })))
 }
+
+pub async fn login(
+    Json(credentials): Json<Credentials>,
+    state: Data<State>,
+    request: HttpRequest,
+) -> Response {
+    credentials.validate()?;
+
+    let invalid_credentials = BadRequest(t("user.invalid_credentials"));
+
+    let user = match state.users.find_one_by_account(credentials.account).await? {
+        Some(user) => user,
+        None => {
+            // as slow as a wrong password, so the timing does not tell which accounts exist
+            User::verify_dummy_password(credentials.password);
+            return Err(invalid_credentials);
+        }
+    };
+
+    if !user.verify_password(credentials.password) {
+        return Err(invalid_credentials);
+    }
+
+    session::login(&request, &user)?;
+
+    let value = user.into_json();
+
+    Ok(HttpResponse::Ok().json(json!({ "user": value })))
+}
+
+pub async fn logout(identity: Option<Identity>) -> Response {
+    let identity = identity.ok_or_else(|| Unauthorized(t("error.login_required")))?;
+
+    // purges the session, which removes it from the redis store as well
+    identity.logout();
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn reset_password(
+    Json(resetter): Json<PasswordResetter>,
+    state: Data<State>,
+) -> Response {
+    resetter.validate()?;
+
+    let email = resetter.email.to_owned();
+    let candidate = resetter.code.to_owned();
+
+    let invalid_code = BadRequest(t("code.invalid_password_reset"));
+
+    let mut user = match state.users.find_one_by_email(email.to_owned()).await? {
+        Some(user) => user,
+        None => return Err(invalid_code),
+    };
+
+    // check if the code is valid, and deactivate it if so
+    if !Code::consume(
+        email,
+        CodeType::PasswordReset,
+        None,
+        candidate,
+        &state.config,
+        state.codes.as_ref(),
+    )
+    .await?
+    {
+        return Err(invalid_code);
+    }
+
+    // end all existing sessions, whoever holds them has to log in with the new password
+    user.update_password(resetter.password, true, state.users.as_ref())
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -2,16 +2,22 @@ use actix_web::{
     web::{Data, Json},
     HttpResponse,
 };
-use rand::{thread_rng, Rng};
 use validator::Validate;
 
 use crate::{
     controllers::Response,
     errors::Error::BadRequest,
-    models::users::{
-        codes::{Code, CodeType::Registration},
-        mail_validator::MailValidator,
-        User,
+    extractors::users::AuthenticatedUser,
+    i18n::{self, t_args},
+    models::{
+        outbox::OutboxMessage,
+        users::{
+            codes::{
+                Code,
+                CodeType::{EmailChange, Registration},
+            },
+            mail_validator::MailValidator,
+        },
     },
     state::State,
 };
@@ -25,53 +31,76 @@ pub async fn send_registration_code(
     let email = email_validator.email;
 
     // check if user with this email already exists
-    if User::find_one_by_email(email.to_owned(), &state.database)
+    if state
+        .users
+        .find_one_by_email(email.to_owned())
         .await?
         .is_some()
     {
-        return Err(BadRequest(format!(
-            "User with email `{}` already exists.",
-            email
-        )));
+        return Err(BadRequest(t_args("user.email_taken", &[("email", &email)])));
     }
 
-    // check if registration code for this email already exists
-    if let Some(code) =
-        Code::find_one_by_email(email.to_owned(), Registration, &state.database).await?
-    {
-        // if code is not expired, return error
-        if !code.is_expired() {
-            return Err(BadRequest(format!(
-                "Registration code for email `{}` already exists, please check your email or try again later.",
-                email
-            )));
-        } else {
-            // if code is expired, deactivate it
-            code.deactivate_by_id(&state.database).await?;
-        }
-    }
+    Code::issue(
+        email,
+        Registration,
+        None,
+        i18n::current(),
+        &state.config,
+        state.codes.as_ref(),
+        state.outbox.as_ref(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().finish())
+}
+
+pub async fn send_password_reset_code(
+    Json(email_validator): Json<MailValidator>,
+    state: Data<State>,
+) -> Response {
+    email_validator.validate()?;
+
+    let email = email_validator.email;
+
+    // the outbox worker looks the user up and issues the code, so the response is the same
+    // whether the user exists or not, and so is the time it takes, the endpoint can not be used
+    // to find out which emails have an account
+    let message = OutboxMessage::password_reset_request(email, i18n::current());
+    state.outbox.enqueue(&message).await?;
 
-    // generate new code
-    let mut rng = thread_rng();
-    let code = rng.gen_range(100000..999999).to_string();
+    Ok(HttpResponse::Created().finish())
+}
 
-    // send email with code
-    state
-        .email
-        .send_registration_code(
-            email.to_owned(),
-            "Register An Account",
-            "Please use the following code to register an account",
-            code.to_owned(),
-            state.config.code_expire,
-        )
-        .await?;
+/// Send a code to the new email of the logged in user
+pub async fn send_email_change_code(
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(email_validator): Json<MailValidator>,
+    state: Data<State>,
+) -> Response {
+    email_validator.validate()?;
 
-    // create new code instance
-    let code = Code::new(email, code, Registration, state.config.code_expire);
+    let email = email_validator.email;
+
+    // check if user with this email already exists
+    if state
+        .users
+        .find_one_by_email(email.to_owned())
+        .await?
+        .is_some()
+    {
+        return Err(BadRequest(t_args("user.email_taken", &[("email", &email)])));
+    }
 
-    // save code to database
-    Code::create(code, &state.database).await?;
+    Code::issue(
+        email,
+        EmailChange,
+        Some(user.id),
+        user.locale(),
+        &state.config,
+        state.codes.as_ref(),
+        state.outbox.as_ref(),
+    )
+    .await?;
 
     Ok(HttpResponse::Created().finish())
 }
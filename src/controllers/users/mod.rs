@@ -1,2 +1,7 @@
+pub mod accounts;
 pub mod auth;
 pub mod codes;
+pub mod profile;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,124 @@
+use actix_session::SessionExt;
+use actix_web::{
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+use serde_json::json;
+use tracing::error;
+use validator::Validate;
+
+use crate::{
+    controllers::Response,
+    errors::Error::BadRequest,
+    extractors::users::AuthenticatedUser,
+    i18n::{t, translate},
+    models::{
+        outbox::OutboxMessage,
+        users::{
+            auth::{EmailChanger, PasswordChanger},
+            codes::{Code, CodeType},
+            User,
+        },
+        IntoJson,
+    },
+    state::State,
+    utils::session,
+};
+
+pub async fn me(AuthenticatedUser(user): AuthenticatedUser) -> Response {
+    let value = user.into_json();
+
+    Ok(HttpResponse::Ok().json(json!({ "user": value })))
+}
+
+pub async fn change_password(
+    AuthenticatedUser(mut user): AuthenticatedUser,
+    Json(changer): Json<PasswordChanger>,
+    state: Data<State>,
+    request: HttpRequest,
+) -> Response {
+    changer.validate()?;
+
+    if !user.verify_password(changer.current_password) {
+        return Err(BadRequest(t("user.current_password_incorrect")));
+    }
+
+    if user.verify_password(changer.password.to_owned()) {
+        return Err(BadRequest(t("user.same_password")));
+    }
+
+    user.update_password(
+        changer.password,
+        changer.end_other_sessions,
+        state.users.as_ref(),
+    )
+    .await?;
+
+    // rotate the session id, and keep the current session alive if the others are ended
+    let session = request.get_session();
+
+    session.renew();
+    session::stamp(&session)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn change_email(
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(changer): Json<EmailChanger>,
+    state: Data<State>,
+) -> Response {
+    changer.validate()?;
+
+    let old_email = user.email().to_owned();
+
+    if changer.email == old_email {
+        return Err(BadRequest(t("user.same_email")));
+    }
+
+    // check if the code sent to the new email for this user is valid, and deactivate it if so
+    if !Code::consume(
+        changer.email.to_owned(),
+        CodeType::EmailChange,
+        Some(user.id),
+        changer.code,
+        &state.config,
+        state.codes.as_ref(),
+    )
+    .await?
+    {
+        return Err(BadRequest(t("code.invalid_verification")));
+    }
+
+    let user = state
+        .users
+        .update_profile(user.id, None, Some(changer.email), None)
+        .await?;
+
+    notify_email_changed(old_email, &user, &state).await;
+
+    let value = user.into_json();
+
+    Ok(HttpResponse::Ok().json(json!({ "user": value })))
+}
+
+/// Tell the old address that the email of the account has been changed,
+/// the change is already saved, so a failure to queue the email is only logged
+async fn notify_email_changed(old_email: String, user: &User, state: &State) {
+    let locale = user.locale();
+
+    let message = OutboxMessage::notification(
+        old_email.to_owned(),
+        locale,
+        translate(locale, "email.email_changed.subject", &[]),
+        translate(locale, "email.email_changed.header", &[]),
+        translate(locale, "user.email_changed", &[("email", user.email())]),
+    );
+
+    if let Err(e) = state.outbox.enqueue(&message).await {
+        error!(
+            "Failed to notify {} of the email change: {:?}",
+            old_email, e
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use actix_identity::IdentityMiddleware;
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::Key,
+    http::StatusCode,
+    test,
+    web::{self, Data},
+    App,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    controllers::users::{auth::register, codes::send_registration_code},
+    models::users::repository::UserRepository,
+    testing::Fixture,
+    workers::outbox::OutboxWorker,
+};
+
+const EMAIL: &str = "alice@example.com";
+
+/// Deliver the queued email and read the code back from it
+async fn deliver_code(fixture: &Fixture) -> String {
+    let worker = OutboxWorker::new(fixture.state.clone());
+
+    assert!(worker.process_one().await.expect("the email is delivered"));
+
+    let messages = fixture.mailer.messages().await;
+    let (to, message) = messages.last().expect("an email is sent");
+    assert_eq!(to, &vec![EMAIL.to_owned()]);
+
+    let marker = "verification code is ";
+    let start = message.find(marker).expect("the email has a code") + marker.len();
+
+    message[start..start + 6].to_owned()
+}
+
+macro_rules! app {
+    ($state:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(Data::new($state))
+                .wrap(IdentityMiddleware::default())
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                ))
+                .route(
+                    "/codes/registration",
+                    web::post().to(send_registration_code),
+                )
+                .route("/register", web::post().to(register)),
+        )
+        .await
+    };
+}
+
+fn registrar(code: &str) -> Value {
+    json!({
+        "email": EMAIL,
+        "username": "alice",
+        "password": "Passw0rd",
+        "passwordConfirm": "Passw0rd",
+        "code": code,
+    })
+}
+
+#[actix_web::test]
+async fn send_registration_code_queues_an_email() {
+    let fixture = Fixture::new();
+    let app = app!(fixture.state.clone());
+
+    let request = test::TestRequest::post()
+        .uri("/codes/registration")
+        .set_json(json!({ "email": EMAIL }))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let messages = fixture.outbox.messages().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].to(), EMAIL);
+
+    // the code is derived when the email is sent, the queue never holds it
+    let message = serde_json::to_value(&messages[0]).unwrap();
+    assert!(message["body"].get("code").is_none());
+}
+
+#[actix_web::test]
+async fn register_with_the_emailed_code() {
+    let fixture = Fixture::new();
+    let app = app!(fixture.state.clone());
+
+    let request = test::TestRequest::post()
+        .uri("/codes/registration")
+        .set_json(json!({ "email": EMAIL }))
+        .to_request();
+    test::call_service(&app, request).await;
+
+    let code = deliver_code(&fixture).await;
+
+    let request = test::TestRequest::post()
+        .uri("/register")
+        .set_json(registrar(&code))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(fixture
+        .users
+        .find_one_by_email(EMAIL.to_owned())
+        .await
+        .unwrap()
+        .is_some());
+
+    // the code is consumed by the registration
+    let request = test::TestRequest::post()
+        .uri("/register")
+        .set_json(registrar(&code))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn register_rejects_a_wrong_code() {
+    let fixture = Fixture::new();
+    let app = app!(fixture.state.clone());
+
+    let request = test::TestRequest::post()
+        .uri("/codes/registration")
+        .set_json(json!({ "email": EMAIL }))
+        .to_request();
+    test::call_service(&app, request).await;
+
+    let code = deliver_code(&fixture).await;
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+
+    let request = test::TestRequest::post()
+        .uri("/register")
+        .set_json(registrar(wrong))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(fixture
+        .users
+        .find_one_by_email(EMAIL.to_owned())
+        .await
+        .unwrap()
+        .is_none());
+}
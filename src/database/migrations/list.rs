@@ -0,0 +1,149 @@
+use futures::future::BoxFuture;
+use mongodb::{
+    bson::{doc, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+use std::time::Duration;
+
+use crate::{
+    config::Config,
+    database::{
+        migrations::{Migration, Step},
+        Collection::{Codes, Outbox, Users},
+        Database,
+    },
+    errors::Error,
+    models::users::codes::Code,
+};
+
+/// All migrations, ordered by version, append new ones to the end and never reorder,
+/// applied migrations never change, so the specs are copied here instead of read from the models
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration::new(
+            1,
+            "create users indexes",
+            vec![Step::CreateIndexes {
+                collection: Users,
+                indexes: vec![
+                    index(
+                        doc! { "email": 1 },
+                        IndexOptions::builder()
+                            .name("email".to_owned())
+                            .unique(true)
+                            .build(),
+                    ),
+                    index(
+                        doc! { "username": 1 },
+                        IndexOptions::builder()
+                            .name("username".to_owned())
+                            .unique(true)
+                            .build(),
+                    ),
+                ],
+            }],
+        ),
+        Migration::new(
+            2,
+            "create codes indexes",
+            vec![Step::CreateIndexes {
+                collection: Codes,
+                indexes: vec![
+                    index(
+                        doc! { "expiredAt": 1 },
+                        IndexOptions::builder()
+                            .name("expiredAt".to_owned())
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    ),
+                    index(
+                        doc! { "email": 1, "codeType": 1, "active": 1 },
+                        IndexOptions::builder()
+                            .name("email_codeType_active".to_owned())
+                            .build(),
+                    ),
+                ],
+            }],
+        ),
+        Migration::new(
+            3,
+            "hash plaintext codes",
+            vec![Step::Custom {
+                description: "replace the plaintext `code` of codes with `codeHash`",
+                run: hash_plaintext_codes,
+            }],
+        ),
+        Migration::new(
+            4,
+            "backfill code attempts",
+            vec![Step::Backfill {
+                collection: Codes,
+                filter: doc! { "attempts": { "$exists": false } },
+                update: doc! { "$set": { "attempts": 0 } },
+            }],
+        ),
+        Migration::new(
+            5,
+            "users and codes schema validators",
+            vec![
+                Step::Validator {
+                    collection: Users,
+                    schema: doc! {
+                        "bsonType": "object",
+                        "required": ["_id", "email", "username", "password", "role", "createdAt", "updatedAt"],
+                        "properties": {
+                            "email": { "bsonType": "string" },
+                            "username": { "bsonType": "string" },
+                            "password": { "bsonType": "string" },
+                            "role": { "enum": ["root", "admin", "author", "user"] },
+                            "createdAt": { "bsonType": "date" },
+                            "updatedAt": { "bsonType": "date" },
+                            "sessionsRevokedAt": { "bsonType": "date" },
+                        },
+                    },
+                },
+                Step::Validator {
+                    collection: Codes,
+                    schema: doc! {
+                        "bsonType": "object",
+                        "required": ["_id", "email", "codeHash", "codeType", "active", "createdAt", "expiredAt"],
+                        "properties": {
+                            "email": { "bsonType": "string" },
+                            "codeHash": { "bsonType": "string" },
+                            "codeType": { "enum": ["registration", "passwordReset", "emailChange"] },
+                            "active": { "bsonType": "bool" },
+                            "attempts": { "bsonType": "int" },
+                            "createdAt": { "bsonType": "date" },
+                            "expiredAt": { "bsonType": "date" },
+                        },
+                    },
+                },
+            ],
+        ),
+        Migration::new(
+            6,
+            "create outbox indexes",
+            vec![Step::CreateIndexes {
+                collection: Outbox,
+                indexes: vec![index(
+                    doc! { "status": 1, "nextAttemptAt": 1 },
+                    IndexOptions::builder()
+                        .name("status_nextAttemptAt".to_owned())
+                        .build(),
+                )],
+            }],
+        ),
+    ]
+}
+
+fn index(keys: Document, options: IndexOptions) -> IndexModel {
+    IndexModel::builder().keys(keys).options(options).build()
+}
+
+fn hash_plaintext_codes<'a>(
+    database: &'a Database,
+    config: &'a Config,
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(Code::hash_plaintext_codes(&config.code_secret, database))
+}
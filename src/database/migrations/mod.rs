@@ -0,0 +1,374 @@
+use actix_web::rt::time::sleep;
+use futures::{future::BoxFuture, TryStreamExt};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    error::ErrorKind,
+    options::UpdateOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::{
+    config::Config,
+    database::{
+        Collection::{self, Migrations},
+        Database, Indexes,
+    },
+    errors::{is_duplicate_key, Error},
+    models::{
+        outbox::OutboxMessage,
+        users::{codes::Code, User},
+    },
+};
+
+mod list;
+
+/// `_id` of the document in `migrations` held while migrations are applied
+const LOCK_ID: &str = "lock";
+/// Longer than any migration should take
+const LOCK_TTL: Duration = Duration::from_secs(600);
+
+/// A versioned change of the database schema, applied once and recorded in `migrations`,
+/// steps should be idempotent, so a migration interrupted halfway can be run again
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    steps: Vec<Step>,
+}
+
+impl Migration {
+    pub fn new(version: i32, name: &'static str, steps: Vec<Step>) -> Self {
+        Self {
+            version,
+            name,
+            steps,
+        }
+    }
+}
+
+type CustomStep = for<'a> fn(&'a Database, &'a Config) -> BoxFuture<'a, Result<(), Error>>;
+
+pub enum Step {
+    /// Create indexes on the collection, existing indexes with the same definition are kept
+    CreateIndexes {
+        collection: Collection,
+        indexes: Vec<IndexModel>,
+    },
+    /// `update_many` the documents matching the filter
+    Backfill {
+        collection: Collection,
+        filter: Document,
+        update: Document,
+    },
+    /// Set the `$jsonSchema` validator of the collection, creating the collection if needed
+    Validator {
+        collection: Collection,
+        schema: Document,
+    },
+    /// Anything the other steps can not express
+    Custom {
+        description: &'static str,
+        run: CustomStep,
+    },
+}
+
+impl Step {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::CreateIndexes {
+                collection,
+                indexes,
+            } => {
+                let names = indexes
+                    .iter()
+                    .filter_map(|index| index.options.as_ref()?.name.to_owned())
+                    .collect::<Vec<_>>();
+                let collection: &str = collection.to_owned().into();
+
+                format!("create indexes [{}] on {}", names.join(", "), collection)
+            }
+            Self::Backfill {
+                collection,
+                filter,
+                update,
+            } => {
+                let collection: &str = collection.to_owned().into();
+
+                format!("update {} matching {} with {}", collection, filter, update)
+            }
+            Self::Validator { collection, .. } => {
+                let collection: &str = collection.to_owned().into();
+
+                format!("set the schema validator of {}", collection)
+            }
+            Self::Custom { description, .. } => description.to_string(),
+        }
+    }
+
+    async fn run(&self, database: &Database, config: &Config) -> Result<(), Error> {
+        match self {
+            Self::CreateIndexes {
+                collection,
+                indexes,
+            } => {
+                database
+                    .collection::<Document>(collection.to_owned())
+                    .create_indexes(indexes.to_owned(), None)
+                    .await?;
+            }
+            Self::Backfill {
+                collection,
+                filter,
+                update,
+            } => {
+                database
+                    .collection::<Document>(collection.to_owned())
+                    .update_many(filter.to_owned(), update.to_owned(), None)
+                    .await?;
+            }
+            Self::Validator { collection, schema } => {
+                let name: &str = collection.to_owned().into();
+
+                let existing = database
+                    .db
+                    .list_collection_names(doc! { "name": name })
+                    .await?;
+
+                if existing.is_empty() {
+                    database.db.create_collection(name, None).await?;
+                }
+
+                database
+                    .db
+                    .run_command(
+                        doc! {
+                            "collMod": name,
+                            "validator": { "$jsonSchema": schema.to_owned() },
+                            "validationLevel": "moderate",
+                        },
+                        None,
+                    )
+                    .await?;
+            }
+            Self::Custom { run, .. } => run(database, config).await?,
+        }
+
+        Ok(())
+    }
+}
+
+/// A migration recorded in the `migrations` collection
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationRecord {
+    #[serde(rename = "_id")]
+    pub version: i32,
+    pub name: String,
+    pub applied_at: DateTime,
+    /// Unit is millisecond
+    pub duration: i64,
+}
+
+/// A migration and when it was applied, `None` if it is pending
+pub struct MigrationStatus<'a> {
+    pub migration: &'a Migration,
+    pub record: Option<MigrationRecord>,
+}
+
+pub struct Migrator<'a> {
+    database: &'a Database,
+    config: &'a Config,
+    migrations: Vec<Migration>,
+}
+
+impl<'a> Migrator<'a> {
+    pub fn new(database: &'a Database, config: &'a Config) -> Self {
+        Self {
+            database,
+            config,
+            migrations: list::migrations(),
+        }
+    }
+
+    pub async fn status(&self) -> Result<Vec<MigrationStatus<'_>>, Error> {
+        // besides the lock
+        let mut records = self
+            .database
+            .collection::<MigrationRecord>(Migrations)
+            .find(doc! { "_id": { "$type": "int" } }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let status = self
+            .migrations
+            .iter()
+            .map(|migration| {
+                let record = records
+                    .iter()
+                    .position(|record| record.version == migration.version)
+                    .map(|index| records.swap_remove(index));
+
+                MigrationStatus { migration, record }
+            })
+            .collect();
+
+        Ok(status)
+    }
+
+    /// Migrations not applied yet, in order
+    pub async fn pending(&self) -> Result<Vec<&Migration>, Error> {
+        let pending = self
+            .status()
+            .await?
+            .into_iter()
+            .filter(|status| status.record.is_none())
+            .map(|status| status.migration)
+            .collect();
+
+        Ok(pending)
+    }
+
+    /// Indexes declared by the models with `Indexes` which no migration has created yet,
+    /// as `collection.name`
+    pub async fn missing_indexes(&self) -> Result<Vec<String>, Error> {
+        let mut missing = self.missing_indexes_of::<User>().await?;
+
+        missing.extend(self.missing_indexes_of::<Code>().await?);
+        missing.extend(self.missing_indexes_of::<OutboxMessage>().await?);
+
+        Ok(missing)
+    }
+
+    async fn missing_indexes_of<T: Indexes>(&self) -> Result<Vec<String>, Error> {
+        let collection = self.database.collection::<Document>(T::COLLECTION);
+        let name: &str = T::COLLECTION.into();
+
+        // the collection does not exist before its first document or migration
+        let existing = match collection.list_index_names().await {
+            Ok(existing) => existing,
+            Err(e) if matches!(e.kind.as_ref(), ErrorKind::Command(error) if error.code == 26) => {
+                Vec::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let missing = T::indexes()
+            .into_iter()
+            .filter_map(|index| index.options?.name)
+            .filter(|index| !existing.contains(index))
+            .map(|index| format!("{}.{}", name, index))
+            .collect();
+
+        Ok(missing)
+    }
+
+    /// Apply the pending migrations in order, stopping at the first failure,
+    /// with `dry_run` the steps are only logged, returns the versions applied,
+    /// instances running it at the same time wait for each other
+    pub async fn run(&self, dry_run: bool) -> Result<Vec<i32>, Error> {
+        if dry_run {
+            return self.apply(true).await;
+        }
+
+        let owner = self.lock().await?;
+        let result = self.apply(false).await;
+
+        self.unlock(&owner).await?;
+
+        result
+    }
+
+    /// Take the lock, waiting while another instance holds it,
+    /// a lock older than `LOCK_TTL` is taken over, its holder is assumed to have died
+    async fn lock(&self) -> Result<String, Error> {
+        let owner = ObjectId::new().to_hex();
+        let collection = self.database.collection::<Document>(Migrations);
+        let mut waiting = false;
+
+        loop {
+            let now = DateTime::now();
+            let expires_at =
+                DateTime::from_millis(now.timestamp_millis() + LOCK_TTL.as_millis() as i64);
+
+            // inserted if absent, replaced if expired, a duplicate `_id` if held by another
+            let result = collection
+                .update_one(
+                    doc! { "_id": LOCK_ID, "expiresAt": { "$lt": now } },
+                    doc! { "$set": { "owner": &owner, "expiresAt": expires_at } },
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await;
+
+            match result {
+                Ok(_) => return Ok(owner),
+                Err(e) if is_duplicate_key(&e) => {
+                    if !waiting {
+                        info!("Waiting for another instance to apply the migrations");
+                        waiting = true;
+                    }
+
+                    sleep(Duration::from_secs(1)).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    async fn unlock(&self, owner: &str) -> Result<(), Error> {
+        self.database
+            .collection::<Document>(Migrations)
+            .delete_one(doc! { "_id": LOCK_ID, "owner": owner }, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn apply(&self, dry_run: bool) -> Result<Vec<i32>, Error> {
+        // read after taking the lock, another instance may have applied them meanwhile
+        let pending = self.pending().await?;
+
+        let mut applied = Vec::new();
+
+        for migration in pending {
+            info!(
+                "{}migration {} {}",
+                if dry_run { "[dry run] " } else { "" },
+                migration.version,
+                migration.name
+            );
+
+            let start = Instant::now();
+
+            for step in migration.steps.iter() {
+                info!("  {}", step.describe());
+
+                if !dry_run {
+                    step.run(self.database, self.config).await?;
+                }
+            }
+
+            if dry_run {
+                continue;
+            }
+
+            let record = MigrationRecord {
+                version: migration.version,
+                name: migration.name.to_owned(),
+                applied_at: DateTime::now(),
+                duration: start.elapsed().as_millis() as i64,
+            };
+
+            self.database
+                .collection::<MigrationRecord>(Migrations)
+                .insert_one(record, None)
+                .await?;
+
+            applied.push(migration.version);
+        }
+
+        Ok(applied)
+    }
+}
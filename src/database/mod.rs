@@ -1,13 +1,13 @@
-use log::{error, info};
 use mongodb::{
-    bson::doc,
-    options::{ClientOptions, IndexOptions},
-    Client, Collection as MongoCollection, Database as MongoDatabase, IndexModel,
+    bson::doc, options::ClientOptions, Client, Collection as MongoCollection,
+    Database as MongoDatabase, IndexModel,
 };
 use std::{process, str::FromStr};
+use tracing::{error, info};
 
-use crate::{config::MongoConfig, models::users::User};
+use crate::{config::MongoConfig, errors::Error};
 
+pub mod migrations;
 pub mod redis;
 
 #[derive(Debug, Clone)]
@@ -17,16 +17,19 @@ pub struct Database {
 
 impl Database {
     pub async fn new(mongo_config: MongoConfig) -> Self {
-        use Collection::*;
-
         let client = Self::connect(&mongo_config.mongo_url).await;
         let db = client.database(&mongo_config.db_name);
 
-        Self::create_unique_indexes::<User>(&db, Users, vec!["email", "username"]).await;
-
         Self { db }
     }
 
+    /// Round trip to the server, for the health checks
+    pub async fn ping(&self) -> Result<(), Error> {
+        self.db.run_command(doc! {"ping": 1}, None).await?;
+
+        Ok(())
+    }
+
     pub fn collection<T>(&self, collection: Collection) -> MongoCollection<T> {
         self.db.collection::<T>(collection.into())
     }
@@ -65,46 +68,22 @@ impl Database {
             }
         }
     }
+}
 
-    async fn create_unique_indexes<T>(
-        database: &MongoDatabase,
-        collection: Collection,
-        keys: Vec<&'static str>,
-    ) {
-        let name: &str = collection.into();
-
-        let connection = database.collection::<T>(name);
-
-        let indexes = keys.into_iter().map(|key| {
-            let options = IndexOptions::builder()
-                .name(key.to_string())
-                .unique(true)
-                .build();
-
-            IndexModel::builder()
-                .keys(doc! {
-                    key: 1
-                })
-                .options(options)
-                .build()
-        });
-
-        match connection.create_indexes(indexes, None).await {
-            Ok(_) => {
-                info!("Created unique indexes for {}", name);
-            }
-            Err(e) => {
-                error!("Failed to create unique indexes for {}: {}", name, e);
-                process::exit(1);
-            }
-        }
-    }
+/// Indexes of the collection a model is stored in, as the model expects them now,
+/// a migration creates them with a copy of the specs, `migrate status` reports the missing ones
+pub trait Indexes {
+    const COLLECTION: Collection;
+
+    fn indexes() -> Vec<IndexModel>;
 }
 
 #[derive(Debug, Clone)]
 pub enum Collection {
     Users,
     Codes,
+    Migrations,
+    Outbox,
 }
 
 impl From<Collection> for &str {
@@ -114,6 +93,8 @@ impl From<Collection> for &str {
         match collection {
             Users => "users",
             Codes => "codes",
+            Migrations => "migrations",
+            Outbox => "outbox",
         }
     }
 }
@@ -127,7 +108,9 @@ impl FromStr for Collection {
         match s {
             "users" => Ok(Users),
             "codes" => Ok(Codes),
-            _ => Err("Invalid collection name, must be one of: users"),
+            "migrations" => Ok(Migrations),
+            "outbox" => Ok(Outbox),
+            _ => Err("Invalid collection name, must be one of: users, codes, migrations, outbox"),
         }
     }
 }
@@ -1,28 +1,62 @@
 use actix_session::storage::RedisSessionStore;
 use actix_web::cookie::Key;
-use log::error;
+use redis::{aio::ConnectionManager, cmd, Client};
 use std::process;
+use tracing::{error, info};
+
+use crate::errors::Error;
 
 #[derive(Clone)]
 pub struct Redis {
     pub key: Key,
+    /// Opens its own connection to the same server
     pub store: RedisSessionStore,
+    /// Shared connection of everything besides the sessions, reconnects automatically
+    pub connection: ConnectionManager,
 }
 
 impl Redis {
     pub async fn new(redis_url: String) -> Self {
         let key = Key::generate();
-        let store = match RedisSessionStore::new(redis_url).await {
-            Ok(store) => {
-                log::info!("Connected to redis");
-                store
+
+        let connection = match Client::open(redis_url.to_owned()) {
+            Ok(client) => match ConnectionManager::new(client).await {
+                Ok(connection) => {
+                    info!("Connected to redis");
+                    connection
+                }
+                Err(err) => {
+                    error!("Failed to connect to redis: {}", err);
+                    process::exit(1);
+                }
+            },
+            Err(err) => {
+                error!("Invalid redis url: {}", err);
+                process::exit(1);
             }
+        };
+
+        let store = match RedisSessionStore::new(redis_url).await {
+            Ok(store) => store,
             Err(err) => {
                 error!("Failed to connect to redis: {}", err);
                 process::exit(1);
             }
         };
 
-        Self { key, store }
+        Self {
+            key,
+            store,
+            connection,
+        }
+    }
+
+    /// Round trip to the server, which also holds the sessions
+    pub async fn ping(&self) -> Result<(), Error> {
+        let mut connection = self.connection.clone();
+
+        cmd("PING").query_async::<_, ()>(&mut connection).await?;
+
+        Ok(())
     }
 }
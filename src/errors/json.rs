@@ -1,24 +1,34 @@
 use actix_web::{
     error::{Error, InternalError, JsonPayloadError},
-    HttpRequest, HttpResponse,
+    http::StatusCode,
+    HttpRequest,
 };
 
-use crate::errors::json;
+use crate::{
+    errors::{ErrorBody, ErrorCode},
+    i18n::t,
+};
 
 pub fn json_error_handler(error: JsonPayloadError, _req: &HttpRequest) -> Error {
     use JsonPayloadError::{ContentType, Deserialize};
 
-    let res = match &error {
-        ContentType => HttpResponse::UnsupportedMediaType().json(json!({
-            "message": "Unsupported content type.",
-        })),
-        Deserialize(error) => HttpResponse::BadRequest().json(json!({
-            "message": error.to_string() + ".",
-        })),
-        _ => HttpResponse::BadRequest().json(json!({
-            "message": "Bad request.",
-        })),
+    let body = match &error {
+        ContentType => ErrorBody::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::UnsupportedMediaType,
+            t("error.unsupported_content_type"),
+        ),
+        Deserialize(error) => ErrorBody::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::BadRequest,
+            error.to_string() + ".",
+        ),
+        _ => ErrorBody::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::BadRequest,
+            t("error.bad_request"),
+        ),
     };
 
-    InternalError::from_response(error, res).into()
+    InternalError::from_response(error, body.respond()).into()
 }
@@ -1,12 +1,21 @@
 use actix_web::{
-    body::BoxBody, http::StatusCode, HttpResponse, HttpResponseBuilder, ResponseError,
+    body::BoxBody,
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpResponse, HttpResponseBuilder, ResponseError,
 };
-use log::error;
-use serde_json::json;
+use serde_json::Map;
 use thiserror::Error as ThisError;
+use tracing::{error, info};
+
+use crate::{i18n::t, metrics};
+
+pub use mongo::is_duplicate_key;
+pub use response::{ErrorBody, ErrorCode, FieldError};
 
 pub mod json;
 mod mongo;
+pub mod query;
+mod response;
 mod validation;
 
 #[derive(Debug, ThisError)]
@@ -19,66 +28,172 @@ pub enum Error {
     LettreError(#[from] lettre::error::Error),
     #[error("Lettre SMTP error: {0}")]
     LettreSmtpError(#[from] lettre::transport::smtp::Error),
+    #[error("Lettre file error: {0}")]
+    LettreFileError(#[from] lettre::transport::file::Error),
+    #[error("Lettre address error: {0}")]
+    LettreAddressError(#[from] lettre::address::AddressError),
+    #[error("Redis error: {0}")]
+    RedisError(#[from] redis::RedisError),
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// The field and the message, like a duplicate key error of the unique indexes
+    #[error("Duplicate: {1}")]
+    Duplicate(String, String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
     #[error("Not found: {0}")]
     NotFound(String),
+    /// The code has been deactivated after too many wrong attempts
+    #[error("Code locked: {0}")]
+    CodeLocked(String),
+    /// The message and the seconds to wait before retrying
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String, u64),
     #[error("Internal server error: {0}")]
     InternalServerError(String),
     #[error("Handlebars render error: {0}")]
     HandlebarsRenderError(#[from] handlebars::RenderError),
     #[error("Handlebars template error: {0}")]
     HandlebarsTemplateError(#[from] Box<handlebars::TemplateError>),
+    #[error("Session get error: {0}")]
+    SessionGetError(#[from] actix_session::SessionGetError),
+    #[error("Session insert error: {0}")]
+    SessionInsertError(#[from] actix_session::SessionInsertError),
     #[error("Anyhow error: {0}")]
     AnyhowError(#[from] anyhow::Error),
 }
 
-impl ResponseError for Error {
-    fn status_code(&self) -> StatusCode {
+impl Error {
+    /// Name of the variant, the label of the error metrics
+    pub fn variant(&self) -> &'static str {
         use Error::*;
 
         match self {
-            MongoDBError(error) => mongo::mongo_error_handler(error).0,
-            ValidationErrors(_) | BadRequest(_) => StatusCode::BAD_REQUEST,
-            LettreError(_)
-            | LettreSmtpError(_)
-            | HandlebarsRenderError(_)
-            | HandlebarsTemplateError(_)
-            | AnyhowError(_)
-            | InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            NotFound(_) => StatusCode::NOT_FOUND,
+            MongoDBError(_) => "MongoDBError",
+            ValidationErrors(_) => "ValidationErrors",
+            LettreError(_) => "LettreError",
+            LettreSmtpError(_) => "LettreSmtpError",
+            LettreFileError(_) => "LettreFileError",
+            LettreAddressError(_) => "LettreAddressError",
+            RedisError(_) => "RedisError",
+            BadRequest(_) => "BadRequest",
+            Unauthorized(_) => "Unauthorized",
+            Duplicate(..) => "Duplicate",
+            Forbidden(_) => "Forbidden",
+            NotFound(_) => "NotFound",
+            CodeLocked(_) => "CodeLocked",
+            TooManyRequests(..) => "TooManyRequests",
+            InternalServerError(_) => "InternalServerError",
+            HandlebarsRenderError(_) => "HandlebarsRenderError",
+            HandlebarsTemplateError(_) => "HandlebarsTemplateError",
+            SessionGetError(_) => "SessionGetError",
+            SessionInsertError(_) => "SessionInsertError",
+            AnyhowError(_) => "AnyhowError",
         }
     }
 
-    fn error_response(&self) -> HttpResponse<BoxBody> {
-        let default_error_message =
-            "Something went wrong. Please try again later or contact us.".to_string();
-
+    /// Body of the error response, the status code is taken from it as well
+    pub fn body(&self) -> ErrorBody {
         use Error::*;
 
-        error!("Error: {:?}", self);
+        let internal = |message: String| {
+            ErrorBody::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Internal,
+                message,
+            )
+        };
 
-        let message = match self {
-            MongoDBError(error) => mongo::mongo_error_handler(error).1,
+        match self {
+            MongoDBError(error) => mongo::mongo_error_handler(error),
             ValidationErrors(error) => validation::validation_error_handler(error),
             LettreError(_)
             | LettreSmtpError(_)
+            | LettreFileError(_)
+            | LettreAddressError(_)
             | HandlebarsRenderError(_)
             | HandlebarsTemplateError(_)
-            | AnyhowError(_) => default_error_message,
-            BadRequest(message) => message.to_string(),
-            NotFound(message) => message.to_string(),
+            | SessionGetError(_)
+            | SessionInsertError(_)
+            | RedisError(_)
+            | AnyhowError(_) => internal(t("error.internal")),
+            BadRequest(message) => ErrorBody::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::BadRequest,
+                message.to_string(),
+            ),
+            Duplicate(field, message) => ErrorBody::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::Duplicate,
+                message.to_string(),
+            )
+            .with_field(
+                field.to_string(),
+                FieldError {
+                    code: "duplicate".to_owned(),
+                    message: message.to_string(),
+                    params: Map::new(),
+                },
+            ),
+            Unauthorized(message) => ErrorBody::new(
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::Unauthorized,
+                message.to_string(),
+            ),
+            Forbidden(message) => ErrorBody::new(
+                StatusCode::FORBIDDEN,
+                ErrorCode::Forbidden,
+                message.to_string(),
+            ),
+            NotFound(message) => ErrorBody::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                message.to_string(),
+            ),
+            CodeLocked(message) => {
+                ErrorBody::new(StatusCode::GONE, ErrorCode::CodeLocked, message.to_string())
+            }
+            TooManyRequests(message, _) => ErrorBody::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::TooManyRequests,
+                message.to_string(),
+            ),
             InternalServerError(message) => {
                 if cfg!(debug_assertions) {
-                    message.to_string()
+                    internal(message.to_string())
                 } else {
-                    default_error_message
+                    internal(t("error.internal"))
                 }
             }
-        };
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.body().status
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let body = self.body();
+
+        metrics::count_error(self.variant());
+
+        // logged in the span of the request, with its id
+        if body.status.is_server_error() {
+            error!(error = ?self, code = ?body.code, "request failed");
+        } else {
+            info!(error = %self, code = ?body.code, "request rejected");
+        }
+
+        let mut builder = HttpResponseBuilder::new(body.status);
+
+        if let Error::TooManyRequests(_, retry_after) = self {
+            builder.insert_header((RETRY_AFTER, retry_after.to_string()));
+        }
 
-        HttpResponseBuilder::new(self.status_code()).json(json!({
-            "message": message,
-        }))
+        body.respond_with(&mut builder)
     }
 }
@@ -4,14 +4,26 @@ use mongodb::error::{
     ErrorKind::{BulkWrite, Command, Write},
     WriteError, WriteFailure,
 };
+use serde_json::Map;
 
-use crate::{database::Collection, utils::regex::REGEX_DUPLICATE_KEY};
+use crate::{
+    database::Collection,
+    errors::{ErrorBody, ErrorCode, FieldError},
+    i18n::{t, t_args},
+    utils::regex::REGEX_DUPLICATE_KEY,
+};
 
-const DEFAULT_ERROR_MESSAGE: &str = "Something went wrong, please try again later or contact us.";
+/// The write was refused by a unique index
+pub fn is_duplicate_key(error: &Error) -> bool {
+    match error.kind.as_ref() {
+        Write(WriteFailure::WriteError(WriteError { code, .. })) => *code == 11000,
+        Command(CommandError { code, .. }) => *code == 11000,
+        _ => false,
+    }
+}
 
-pub fn mongo_error_handler(error: &Error) -> (StatusCode, String) {
+pub fn mongo_error_handler(error: &Error) -> ErrorBody {
     let error_kind = error.kind.as_ref();
-    let default_error_message = DEFAULT_ERROR_MESSAGE.to_string();
 
     match error_kind {
         Write(WriteFailure::WriteError(WriteError { code, message, .. })) if *code == 11000 => {
@@ -26,16 +38,14 @@ pub fn mongo_error_handler(error: &Error) -> (StatusCode, String) {
                 }
             }
 
-            (StatusCode::INTERNAL_SERVER_ERROR, default_error_message)
+            internal()
         }
         Command(CommandError { code, message, .. }) if *code == 11000 => capture(message),
-        _ => (StatusCode::INTERNAL_SERVER_ERROR, default_error_message),
+        _ => internal(),
     }
 }
 
-fn capture(message: &str) -> (StatusCode, String) {
-    let default_error_message = DEFAULT_ERROR_MESSAGE.to_string();
-
+fn capture(message: &str) -> ErrorBody {
     if let Some(captures) = REGEX_DUPLICATE_KEY.captures(message) {
         use Collection::*;
 
@@ -45,18 +55,34 @@ fn capture(message: &str) -> (StatusCode, String) {
 
         let current = collection.parse::<Collection>().unwrap();
 
-        match (current, field) {
-            (Users, "email") => (
-                StatusCode::BAD_REQUEST,
-                format!("Email: `{}` already exists.", value),
-            ),
-            (Users, "username") => (
-                StatusCode::BAD_REQUEST,
-                format!("Username: `{}` already exists.", value),
-            ),
-            _ => (StatusCode::INTERNAL_SERVER_ERROR, default_error_message),
-        }
+        let message = match (current, field) {
+            (Users, "email") => t_args("user.email_exists", &[("email", value)]),
+            (Users, "username") => t_args("user.username_exists", &[("username", value)]),
+            _ => return internal(),
+        };
+
+        ErrorBody::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::Duplicate,
+            message.to_owned(),
+        )
+        .with_field(
+            field.to_owned(),
+            FieldError {
+                code: "duplicate".to_owned(),
+                message,
+                params: Map::new(),
+            },
+        )
     } else {
-        (StatusCode::INTERNAL_SERVER_ERROR, default_error_message)
+        internal()
     }
 }
+
+fn internal() -> ErrorBody {
+    ErrorBody::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Internal,
+        t("error.internal"),
+    )
+}
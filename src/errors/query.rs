@@ -0,0 +1,18 @@
+use actix_web::{
+    error::{Error, InternalError},
+    http::StatusCode,
+    HttpRequest,
+};
+use serde_qs::Error as QsError;
+
+use crate::errors::{ErrorBody, ErrorCode};
+
+pub fn query_error_handler(error: QsError, _req: &HttpRequest) -> Error {
+    let body = ErrorBody::new(
+        StatusCode::BAD_REQUEST,
+        ErrorCode::BadRequest,
+        error.to_string() + ".",
+    );
+
+    InternalError::from_response(error, body.respond()).into()
+}
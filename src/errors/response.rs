@@ -0,0 +1,105 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    HttpResponse, HttpResponseBuilder,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::middlewares::request_context;
+
+/// Stable identifier of an error, for clients to match on instead of the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    /// Some fields are invalid, see `errors`
+    ValidationFailed,
+    /// A unique field is already used, see `errors`
+    Duplicate,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    CodeLocked,
+    TooManyRequests,
+    UnsupportedMediaType,
+    Internal,
+}
+
+/// Error of one field, `code` is the validator code, like `invalid_email`
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+}
+
+/// Body of every error response:
+/// `{ "code", "status", "message", "errors", "requestId" }` by default,
+/// or RFC 7807 `application/problem+json` when the client accepts it
+#[derive(Debug, Clone)]
+pub struct ErrorBody {
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub message: String,
+    /// Keyed by the name of the field in the request, like `passwordConfirm`
+    pub errors: BTreeMap<String, Vec<FieldError>>,
+}
+
+impl ErrorBody {
+    pub fn new(status: StatusCode, code: ErrorCode, message: String) -> Self {
+        Self {
+            status,
+            code,
+            message,
+            errors: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_field(mut self, field: String, error: FieldError) -> Self {
+        self.errors.entry(field).or_default().push(error);
+
+        self
+    }
+
+    pub fn respond(&self) -> HttpResponse {
+        self.respond_with(&mut HttpResponseBuilder::new(self.status))
+    }
+
+    /// Finish the builder, which may already have headers like `Retry-After`
+    pub fn respond_with(&self, builder: &mut HttpResponseBuilder) -> HttpResponse {
+        let context = request_context::current();
+        let request_id = context.as_ref().map(|context| context.id.to_owned());
+
+        let mut body = if context.is_some_and(|context| context.problem_json) {
+            builder.content_type("application/problem+json");
+
+            json!({
+                "type": "about:blank",
+                "title": self.status.canonical_reason().unwrap_or_default(),
+                "status": self.status.as_u16(),
+                "detail": self.message,
+                "code": self.code,
+            })
+        } else {
+            builder.content_type(ContentType::json());
+
+            json!({
+                "code": self.code,
+                "status": self.status.as_u16(),
+                "message": self.message,
+            })
+        };
+
+        if !self.errors.is_empty() {
+            body["errors"] = json!(self.errors);
+        }
+
+        if let Some(request_id) = request_id {
+            body["requestId"] = json!(request_id);
+        }
+
+        builder.body(body.to_string())
+    }
+}
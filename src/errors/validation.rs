@@ -1,51 +1,175 @@
-use validator::{ValidationErrors, ValidationErrorsKind};
+use actix_web::http::StatusCode;
+use serde_json::Map;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
 
-pub fn validation_error_handler(error: &ValidationErrors) -> String {
-    let mut message = match_kind(error).join(", ");
+use crate::{
+    errors::{ErrorBody, ErrorCode, FieldError},
+    i18n::{self, t},
+};
 
-    message += ".";
+/// All messages joined as the message of the response, and each error under its field
+pub fn validation_error_handler(error: &ValidationErrors) -> ErrorBody {
+    let mut fields = Vec::new();
 
-    message
+    collect(error, "", &mut fields);
+
+    let mut messages = fields
+        .iter()
+        .map(|(_, error)| error.message.to_owned())
+        .collect::<Vec<_>>();
+
+    messages.sort();
+    messages.dedup();
+
+    let mut message = messages.join(&t("punctuation.separator"));
+
+    message += &t("punctuation.end");
+
+    fields.into_iter().fold(
+        ErrorBody::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationFailed,
+            message,
+        ),
+        |body, (field, error)| body.with_field(field, error),
+    )
 }
 
-fn match_kind(errors: &ValidationErrors) -> Vec<String> {
-    let mut messages = Vec::new();
-    let map = errors.to_owned().into_errors();
+/// Field errors keyed by their path in the request, like `items[0].name`
+fn collect(errors: &ValidationErrors, prefix: &str, fields: &mut Vec<(String, FieldError)>) {
+    for (field, kind) in errors.errors() {
+        let path = format!("{}{}", prefix, camel_case(field));
 
-    for kind in map.values() {
         match kind {
             ValidationErrorsKind::Field(errors) => {
-                let mut local = Vec::new();
-
-                for error in errors.iter().cloned() {
-                    let msg = error.message.unwrap().to_string();
-
-                    local.push(msg);
+                for error in errors {
+                    fields.push((path.to_owned(), field_error(error)));
                 }
-
-                messages.extend(local)
             }
             ValidationErrorsKind::Struct(errors) => {
-                let errors = errors.as_ref();
-                let local = match_kind(errors);
-                messages.extend(local);
+                collect(errors, &format!("{}.", path), fields);
             }
             ValidationErrorsKind::List(errors_map) => {
-                let errors = errors_map.values();
-                let mut local = Vec::new();
-
-                for error in errors {
-                    let errors = error.as_ref();
-                    local.extend(match_kind(errors));
+                for (index, errors) in errors_map {
+                    collect(errors, &format!("{}[{}].", path, index), fields);
                 }
-
-                messages.extend(local);
             }
         }
     }
+}
 
-    messages.sort();
-    messages.dedup();
+fn field_error(error: &ValidationError) -> FieldError {
+    // the submitted value is left out, it may be a password
+    let params = error
+        .params
+        .iter()
+        .filter(|(name, _)| *name != "value")
+        .map(|(name, value)| (name.to_string(), value.to_owned()))
+        .collect::<Map<_, _>>();
+
+    FieldError {
+        code: error.code.to_string(),
+        message: localize(error),
+        params,
+    }
+}
+
+/// The code of the error is the key of its message in the catalogs, `validation.{code}`
+fn localize(error: &ValidationError) -> String {
+    let key = format!("validation.{}", error.code);
+
+    match i18n::lookup(i18n::current(), &key) {
+        Some(message) => message.to_owned(),
+        None => match &error.message {
+            Some(message) => message.to_string(),
+            None => error.code.to_string(),
+        },
+    }
+}
+
+/// The requests are deserialized with `rename_all = "camelCase"`
+fn camel_case(field: &str) -> String {
+    let mut parts = field.split('_');
+    let mut name = parts.next().unwrap_or_default().to_owned();
+
+    for part in parts {
+        let mut chars = part.chars();
+
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use validator::Validate;
+
+    use super::{camel_case, validation_error_handler};
+    use crate::errors::ErrorCode;
 
-    messages
+    #[derive(Validate)]
+    struct Item {
+        #[validate(length(min = 1, code = "name_required"))]
+        item_name: String,
+    }
+
+    #[derive(Validate)]
+    struct Form {
+        #[validate(email(code = "invalid_email"))]
+        email_address: String,
+        #[validate(must_match(other = "email_address", code = "email_mismatch"))]
+        email_confirm: String,
+        #[validate]
+        line_items: Vec<Item>,
+    }
+
+    #[test]
+    fn convert_field_names_to_camel_case() {
+        assert_eq!(camel_case("password_confirm"), "passwordConfirm");
+        assert_eq!(camel_case("email"), "email");
+        assert_eq!(camel_case("created_from_date"), "createdFromDate");
+    }
+
+    #[test]
+    fn report_every_field_under_its_camel_case_path() {
+        let form = Form {
+            email_address: "alice".to_owned(),
+            email_confirm: "bob".to_owned(),
+            line_items: vec![
+                Item {
+                    item_name: "book".to_owned(),
+                },
+                Item {
+                    item_name: String::new(),
+                },
+            ],
+        };
+
+        let body = validation_error_handler(&form.validate().unwrap_err());
+
+        assert_eq!(body.status, StatusCode::BAD_REQUEST);
+        assert!(matches!(body.code, ErrorCode::ValidationFailed));
+        assert_eq!(
+            body.errors.keys().collect::<Vec<_>>(),
+            vec!["emailAddress", "emailConfirm", "lineItems[1].itemName"]
+        );
+
+        let email = &body.errors["emailAddress"][0];
+        assert_eq!(email.code, "invalid_email");
+        // translated from the catalog, and the submitted value is left out
+        assert_ne!(email.message, "invalid_email");
+        assert!(!email.params.contains_key("value"));
+        assert!(body.message.contains(&email.message));
+
+        // without a message in the catalogs, the code is the message
+        assert_eq!(
+            body.errors["lineItems[1].itemName"][0].message,
+            "name_required"
+        );
+    }
 }
@@ -0,0 +1,75 @@
+use actix_identity::Identity;
+use actix_session::SessionExt;
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpMessage, HttpRequest};
+use futures::future::LocalBoxFuture;
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    errors::Error::{self, InternalServerError, Unauthorized},
+    i18n::t,
+    models::users::User,
+    state::State,
+    utils::session,
+};
+
+/// Extracts the logged in user from the identity of the request,
+/// responds with `401 Unauthorized` if there is no identity or the user no longer exists
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser(pub User);
+
+impl AuthenticatedUser {
+    async fn authenticate(request: HttpRequest) -> Result<Self, Error> {
+        // already loaded by a middleware like `RequireRole`
+        if let Some(user) = request.extensions().get::<Self>() {
+            return Ok(user.to_owned());
+        }
+
+        let identity = Identity::extract(&request)
+            .await
+            .map_err(|_| Unauthorized(t("error.login_required")))?;
+
+        let state = match request.app_data::<Data<State>>() {
+            Some(state) => state,
+            None => {
+                return Err(InternalServerError(
+                    "State is not registered as app data".to_owned(),
+                ))
+            }
+        };
+
+        let session_expired = Unauthorized(t("error.session_expired"));
+
+        let id = match identity.id().map(ObjectId::parse_str) {
+            Ok(Ok(id)) => id,
+            _ => {
+                identity.logout();
+                return Err(session_expired);
+            }
+        };
+
+        let logged_in_at = session::logged_in_at(&request.get_session())?;
+
+        match state.users.find_one_by_id(id).await? {
+            // the sessions of the user have been ended since logging in
+            Some(user) if user.is_session_revoked(logged_in_at) => {
+                identity.logout();
+                Err(session_expired)
+            }
+            Some(user) => Ok(Self(user)),
+            None => {
+                // the user has been deleted since logging in
+                identity.logout();
+                Err(session_expired)
+            }
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        Box::pin(Self::authenticate(request.to_owned()))
+    }
+}
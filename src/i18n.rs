@@ -0,0 +1,187 @@
+use lazy_static::lazy_static;
+use mongodb::bson::Bson;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, future::Future};
+
+lazy_static! {
+    static ref EN: HashMap<String, String> = parse(include_str!("assets/locales/en.json"));
+    static ref ZH: HashMap<String, String> = parse(include_str!("assets/locales/zh.json"));
+}
+
+tokio::task_local! {
+    /// Locale of the request being handled, set by `middlewares::locale::Localization`
+    static LOCALE: Locale;
+}
+
+/// Languages with a message catalog, english is used for keys missing in the others
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Zh,
+}
+
+impl Locale {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Zh => "zh",
+        }
+    }
+
+    /// Match a language tag like `zh-CN` by its primary language
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_lowercase();
+
+        match primary.as_str() {
+            "en" => Some(Locale::En),
+            "zh" => Some(Locale::Zh),
+            _ => None,
+        }
+    }
+
+    /// The supported language with the highest quality in an `Accept-Language` header
+    pub fn negotiate(accept_language: &str) -> Option<Self> {
+        let mut best: Option<(Self, f32)> = None;
+
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or_default();
+
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok());
+
+            if let (Some(locale), Some(quality)) = (Self::from_tag(tag), quality) {
+                if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                    best = Some((locale, quality));
+                }
+            }
+        }
+
+        best.map(|(locale, _)| locale)
+    }
+
+    fn catalog(&self) -> &'static HashMap<String, String> {
+        match self {
+            Locale::En => &EN,
+            Locale::Zh => &ZH,
+        }
+    }
+}
+
+impl From<Locale> for Bson {
+    fn from(locale: Locale) -> Self {
+        Bson::String(locale.tag().to_owned())
+    }
+}
+
+/// Locale of the current request, the default one outside of requests
+pub fn current() -> Locale {
+    LOCALE.try_with(|locale| *locale).unwrap_or_default()
+}
+
+/// Run the future with `current()` returning the given locale
+pub async fn scope<F: Future>(locale: Locale, future: F) -> F::Output {
+    LOCALE.scope(locale, future).await
+}
+
+/// Message of the key in the current locale
+pub fn t(key: &str) -> String {
+    translate(current(), key, &[])
+}
+
+/// Message of the key in the current locale, with `{name}` placeholders replaced by the args
+pub fn t_args(key: &str, args: &[(&str, &str)]) -> String {
+    translate(current(), key, args)
+}
+
+pub fn translate(locale: Locale, key: &str, args: &[(&str, &str)]) -> String {
+    let mut message = match lookup(locale, key) {
+        Some(message) => message.to_owned(),
+        None => {
+            tracing::warn!("Missing message `{}` in the catalogs", key);
+            key.to_owned()
+        }
+    };
+
+    for (name, value) in args {
+        message = message.replace(&format!("{{{}}}", name), value);
+    }
+
+    message
+}
+
+pub fn lookup(locale: Locale, key: &str) -> Option<&'static str> {
+    locale
+        .catalog()
+        .get(key)
+        .or_else(|| EN.get(key))
+        .map(String::as_str)
+}
+
+fn parse(catalog: &str) -> HashMap<String, String> {
+    serde_json::from_str(catalog).expect("Invalid message catalog")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, scope, t_args, Locale, EN, ZH};
+
+    #[test]
+    fn match_tags_by_primary_language() {
+        assert_eq!(Locale::from_tag("zh-CN"), Some(Locale::Zh));
+        assert_eq!(Locale::from_tag(" EN_us "), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr"), None);
+        assert_eq!(Locale::from_tag("*"), None);
+    }
+
+    #[test]
+    fn negotiate_by_quality() {
+        assert_eq!(Locale::negotiate("zh-CN"), Some(Locale::Zh));
+        assert_eq!(Locale::negotiate("en;q=0.5, zh;q=0.8"), Some(Locale::Zh));
+        assert_eq!(Locale::negotiate("zh;q=0.3,en"), Some(Locale::En));
+        // the first of equal qualities
+        assert_eq!(Locale::negotiate("en, zh"), Some(Locale::En));
+    }
+
+    #[test]
+    fn negotiate_skips_unknown_and_refused_languages() {
+        assert_eq!(
+            Locale::negotiate("fr-FR, de;q=0.9, zh;q=0.1"),
+            Some(Locale::Zh)
+        );
+        assert_eq!(Locale::negotiate("zh;q=0, en;q=0.2"), Some(Locale::En));
+        assert_eq!(Locale::negotiate("zh;q=high"), None);
+        assert_eq!(Locale::negotiate("fr, *"), None);
+        assert_eq!(Locale::negotiate(""), None);
+    }
+
+    #[test]
+    fn catalogs_have_the_same_keys() {
+        let mut en = EN.keys().collect::<Vec<_>>();
+        let mut zh = ZH.keys().collect::<Vec<_>>();
+        en.sort();
+        zh.sort();
+
+        assert_eq!(en, zh);
+    }
+
+    #[actix_web::test]
+    async fn translate_in_the_scoped_locale() {
+        let message = scope(Locale::Zh, async {
+            t_args("user.email_taken", &[("email", "alice@example.com")])
+        })
+        .await;
+
+        assert_eq!(
+            Some(message.replace("alice@example.com", "{email}").as_str()),
+            lookup(Locale::Zh, "user.email_taken")
+        );
+        assert_ne!(
+            lookup(Locale::Zh, "user.email_taken"),
+            lookup(Locale::En, "user.email_taken")
+        );
+    }
+}
@@ -2,7 +2,14 @@ pub mod config;
 pub mod controllers;
 pub mod database;
 pub mod errors;
+pub mod extractors;
+pub mod i18n;
+pub mod metrics;
+pub mod middlewares;
 pub mod models;
 pub mod routes;
 pub mod state;
+#[cfg(test)]
+pub mod testing;
 pub mod utils;
+pub mod workers;
@@ -1,41 +1,287 @@
 use actix_identity::IdentityMiddleware;
 use actix_session::{config::PersistentSession, SessionMiddleware};
-use actix_web::{cookie::time::Duration, middleware::Logger, web::Data, App, HttpServer};
+use actix_web::{cookie::time::Duration, middleware::Condition, web::Data, App, HttpServer};
 use dotenv::dotenv;
-use env_logger::{init_from_env, Env};
+use futures::future::try_join_all;
+use std::{env, process, sync::Arc};
+use tracing::{error, info, warn};
 
-use headiron_rust::{routes::configure, state::State};
+use headiron_rust::{
+    config::{Config, Listener, Source},
+    database::{migrations::Migrator, redis::Redis, Database},
+    errors::Error,
+    middlewares::{
+        https_redirect::HttpsRedirect, locale::Localization, metrics::Metrics,
+        rate_limit::RateLimitStore, request_context::RequestContext,
+    },
+    routes::{self, configure},
+    state::State,
+    utils::{
+        logging, shutdown,
+        systemd::{self, InheritedListener},
+        tls::CertResolver,
+    },
+    workers::outbox::OutboxWorker,
+};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    init_from_env(Env::new().default_filter_or("info"));
+    logging::init();
 
-    let state = State::new().await;
+    let args = env::args().skip(1).collect::<Vec<_>>();
 
-    let config = state.config.to_owned();
-    let addrs = config.addrs;
+    if let Some(command) = args.first() {
+        return match command.as_str() {
+            "migrate" => migrate(&args[1..]).await,
+            "--check-config" => check_config(),
+            _ => {
+                error!(
+                    "Unknown command `{}`, available commands: migrate, --check-config",
+                    command
+                );
+                process::exit(2);
+            }
+        };
+    }
 
-    log::info!("Starting server at: {:?}", addrs);
+    let config = load_config();
+    let database = Database::new(config.mongo_config.to_owned()).await;
 
-    HttpServer::new(move || {
-        let redis = state.redis.to_owned();
+    if config.migrate_on_startup {
+        if let Err(e) = Migrator::new(&database, &config).run(false).await {
+            error!("Failed to run migrations: {}", e);
+            process::exit(1);
+        }
+    } else {
+        warn_pending_migrations(&database, &config).await;
+    }
+
+    let redis = Redis::new(config.redis_url.to_owned()).await;
+    let listeners = config.listeners.to_owned();
+    let tls_config = config.tls_config.to_owned();
+    // plain requests are redirected to the first HTTPS listener
+    let redirect = tls_config
+        .as_ref()
+        .filter(|tls_config| tls_config.redirect)
+        .map(|tls_config| tls_config.port());
+    let shutdown_delay = config.health_config.shutdown_delay;
+    let metrics_listen = config.metrics_listen;
+    let metrics_on_listeners = config.metrics_on_listeners();
+    let state = State::new(config, &database);
+    let shutdown = state.shutdown.clone();
+
+    actix_web::rt::spawn(OutboxWorker::new(state.clone()).run());
+
+    let mut server = HttpServer::new(move || {
+        let redis = redis.to_owned();
 
         App::new()
             .app_data(Data::new(state.clone()))
+            .app_data(Data::new(redis.clone()))
+            .app_data(Data::from(
+                Arc::new(redis.clone()) as Arc<dyn RateLimitStore>
+            ))
+            .wrap(Localization)
             .wrap(IdentityMiddleware::default())
             .wrap(
                 SessionMiddleware::builder(redis.store, redis.key)
                     .cookie_name("headiron-session".to_string())
-                    .cookie_secure(cfg!(release))
+                    .cookie_secure(!cfg!(debug_assertions) || redirect.is_some())
                     .session_lifecycle(PersistentSession::default().session_ttl(Duration::days(3)))
                     .build(),
             )
-            .wrap(Logger::new("%a %r %s"))
+            .wrap(Condition::new(
+                redirect.is_some(),
+                HttpsRedirect::new(redirect.unwrap_or_default()),
+            ))
+            .wrap(Metrics)
+            .wrap(RequestContext)
             .configure(configure)
-    })
-    .bind(addrs)?
-    .run()
-    .await
+            .configure(|config| {
+                if metrics_on_listeners {
+                    routes::metrics(config);
+                }
+            })
+    });
+
+    for listener in listeners {
+        info!("Listening on {}", listener);
+
+        server = match listener {
+            Listener::Tcp(addr) => server.bind(addr)?,
+            Listener::Unix(path) => server.bind_uds(path)?,
+            Listener::Systemd => {
+                for inherited in systemd::listeners()? {
+                    server = match inherited {
+                        InheritedListener::Tcp(listener) => server.listen(listener)?,
+                        InheritedListener::Unix(listener) => server.listen_uds(listener)?,
+                    };
+                }
+
+                server
+            }
+        };
+    }
+
+    if let Some(tls_config) = tls_config {
+        let resolver = CertResolver::new(&tls_config)?;
+
+        for addr in &tls_config.listeners {
+            info!("Listening on https://{}", addr);
+
+            server = server.bind_rustls(addr, resolver.server_config())?;
+        }
+
+        actix_web::rt::spawn(resolver.clone().reload_on_hangup());
+        actix_web::rt::spawn(resolver.reload_on_change(tls_config.reload_interval));
+    }
+
+    // the signals are handled by `on_signal`, which reports not ready before stopping
+    let mut servers = vec![server.disable_signals().run()];
+
+    if let Some(addr) = metrics_listen {
+        info!("Serving the metrics on {}", addr);
+
+        let admin = HttpServer::new(|| App::new().configure(routes::metrics))
+            .workers(1)
+            .bind(addr)?
+            .disable_signals()
+            .run();
+
+        servers.push(admin);
+    }
+
+    actix_web::rt::spawn(shutdown::on_signal(
+        servers.iter().map(|server| server.handle()).collect(),
+        shutdown,
+        shutdown_delay,
+    ));
+
+    try_join_all(servers).await.map(|_| ())
+}
+
+/// Without the migrations the indexes are missing, expired codes are never removed for instance
+async fn warn_pending_migrations(database: &Database, config: &Config) {
+    match Migrator::new(database, config).pending().await {
+        Ok(pending) if pending.is_empty() => {}
+        Ok(pending) => {
+            let versions = pending
+                .iter()
+                .map(|migration| format!("{} {}", migration.version, migration.name))
+                .collect::<Vec<_>>();
+
+            warn!(
+                "{} pending migrations, run `migrate` to apply them: {}",
+                versions.len(),
+                versions.join(", ")
+            );
+        }
+        Err(e) => warn!("Failed to check the pending migrations: {}", e),
+    }
+}
+
+/// `migrate [status | --dry-run]`, apply the pending migrations or print the status of all
+async fn migrate(args: &[String]) -> std::io::Result<()> {
+    let config = load_config();
+    let database = Database::new(config.mongo_config.to_owned()).await;
+    let migrator = Migrator::new(&database, &config);
+
+    let result = match args.first().map(String::as_str) {
+        Some("status") => print_migration_status(&migrator).await,
+        Some("--dry-run") => migrator.run(true).await.map(|_| ()),
+        None => migrator
+            .run(false)
+            .await
+            .map(|applied| println!("Applied {} migrations", applied.len())),
+        Some(arg) => {
+            error!(
+                "Unknown argument `{}`, usage: migrate [status | --dry-run]",
+                arg
+            );
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        error!("Failed to run migrations: {}", e);
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Every migration and when it was applied, then the indexes of the models no migration creates
+async fn print_migration_status(migrator: &Migrator<'_>) -> Result<(), Error> {
+    for status in migrator.status().await? {
+        let state = match status.record {
+            Some(record) => format!(
+                "applied at {} in {}ms",
+                record
+                    .applied_at
+                    .try_to_rfc3339_string()
+                    .unwrap_or_default(),
+                record.duration
+            ),
+            None => "pending".to_owned(),
+        };
+
+        println!(
+            "{:>4}  {:<40} {}",
+            status.migration.version, status.migration.name, state
+        );
+    }
+
+    for index in migrator.missing_indexes().await? {
+        println!(
+            "missing index {}, declared by its model but not in the database",
+            index
+        );
+    }
+
+    Ok(())
+}
+
+/// `--check-config`, print the effective configuration with secrets redacted, or every issue
+fn check_config() -> std::io::Result<()> {
+    let source = Source::load();
+
+    match Config::effective(&source) {
+        Ok(entries) => {
+            println!("profile = {}", source.profile().name());
+
+            for entry in entries {
+                println!("{:<28} = {:<40} ({})", entry.key, entry.value, entry.origin);
+            }
+
+            if let Ok(config) = Config::from_source(&source) {
+                for warning in config.warnings() {
+                    eprintln!("warning: {}", warning);
+                }
+            }
+
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn load_config() -> Config {
+    match Config::load() {
+        Ok(config) => {
+            for warning in config.warnings() {
+                warn!("{}", warning);
+            }
+
+            config
+        }
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,115 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, histogram_opts, opts, Encoder, HistogramTimer, HistogramVec,
+    IntCounterVec, Registry, TextEncoder,
+};
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    static ref HTTP_REQUESTS: IntCounterVec = counter(
+        "http_requests_total",
+        "HTTP requests by method, route pattern and status",
+        &["method", "route", "status"],
+    );
+    static ref HTTP_DURATION: HistogramVec = histogram(
+        "http_request_duration_seconds",
+        "Time to answer HTTP requests by method and route pattern",
+        &["method", "route"],
+        prometheus::DEFAULT_BUCKETS.to_vec(),
+    );
+    static ref ERRORS: IntCounterVec = counter(
+        "http_errors_total",
+        "Error responses by variant of `errors::Error`",
+        &["variant"],
+    );
+    static ref MONGO_DURATION: HistogramVec = histogram(
+        "mongo_operation_duration_seconds",
+        "Time of the operations of the repositories by collection and operation",
+        &["collection", "operation"],
+        exponential_buckets(0.0005, 2.0, 14).unwrap(),
+    );
+    static ref REDIS_DURATION: HistogramVec = histogram(
+        "redis_operation_duration_seconds",
+        "Time of the redis operations besides sessions",
+        &["operation"],
+        exponential_buckets(0.0005, 2.0, 14).unwrap(),
+    );
+    static ref EMAILS: IntCounterVec = counter(
+        "emails_total",
+        "Emails handed to the mailer by template and result",
+        &["template", "result"],
+    );
+    static ref ARGON2_DURATION: HistogramVec = histogram(
+        "argon2_duration_seconds",
+        "Time of hashing and verifying passwords",
+        &["operation"],
+        exponential_buckets(0.005, 2.0, 10).unwrap(),
+    );
+}
+
+fn counter(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(opts!(name, help), labels).unwrap();
+
+    REGISTRY.register(Box::new(counter.clone())).unwrap();
+
+    counter
+}
+
+fn histogram(name: &str, help: &str, labels: &[&str], buckets: Vec<f64>) -> HistogramVec {
+    let histogram = HistogramVec::new(histogram_opts!(name, help, buckets), labels).unwrap();
+
+    REGISTRY.register(Box::new(histogram.clone())).unwrap();
+
+    histogram
+}
+
+/// `route` is the pattern of the matched resource, like `/api/v1/users/{id}`,
+/// so the ids do not make new series
+pub fn observe_request(method: &str, route: &str, status: u16, seconds: f64) {
+    HTTP_REQUESTS
+        .with_label_values(&[method, route, &status.to_string()])
+        .inc();
+    HTTP_DURATION
+        .with_label_values(&[method, route])
+        .observe(seconds);
+}
+
+pub fn count_error(variant: &str) {
+    ERRORS.with_label_values(&[variant]).inc();
+}
+
+/// Observes the time until it is dropped
+pub fn mongo_timer(collection: &str, operation: &str) -> HistogramTimer {
+    MONGO_DURATION
+        .with_label_values(&[collection, operation])
+        .start_timer()
+}
+
+/// Observes the time until it is dropped
+pub fn redis_timer(operation: &str) -> HistogramTimer {
+    REDIS_DURATION.with_label_values(&[operation]).start_timer()
+}
+
+pub fn count_email(template: &str, sent: bool) {
+    let result = if sent { "sent" } else { "failed" };
+
+    EMAILS.with_label_values(&[template, result]).inc();
+}
+
+/// Observes the time until it is dropped
+pub fn argon2_timer(operation: &str) -> HistogramTimer {
+    ARGON2_DURATION
+        .with_label_values(&[operation])
+        .start_timer()
+}
+
+/// Every metric in the Prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .unwrap();
+
+    String::from_utf8(buffer).unwrap_or_default()
+}
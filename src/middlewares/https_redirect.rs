@@ -0,0 +1,198 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HOST, LOCATION},
+    HttpResponse,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+/// Probes of orchestrators and load balancers are usually plain HTTP
+const EXEMPT_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// Redirects the requests of the plain HTTP listeners to the HTTPS one on `port`
+/// with `308 Permanent Redirect`, which keeps the method and the body,
+/// except the health checks and the requests of the unix sockets, which
+/// are a proxy's that terminates TLS itself
+#[derive(Debug, Clone)]
+pub struct HttpsRedirect {
+    port: u16,
+}
+
+impl HttpsRedirect {
+    pub fn new(port: u16) -> Self {
+        Self { port }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for HttpsRedirect
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = HttpsRedirectMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(HttpsRedirectMiddleware {
+            service: Rc::new(service),
+            port: self.port,
+        }))
+    }
+}
+
+pub struct HttpsRedirectMiddleware<S> {
+    service: Rc<S>,
+    port: u16,
+}
+
+impl<S, B> Service<ServiceRequest> for HttpsRedirectMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        // set by the listener, unlike the scheme of `connection_info` which clients can forge,
+        // the unix sockets have no peer address and would redirect to themselves
+        if request.app_config().secure()
+            || request.peer_addr().is_none()
+            || EXEMPT_PATHS.contains(&request.path())
+        {
+            let service = Rc::clone(&self.service);
+
+            return Box::pin(async move {
+                service
+                    .call(request)
+                    .await
+                    .map(ServiceResponse::map_into_left_body)
+            });
+        }
+
+        let location = self.location(&request);
+        let response = HttpResponse::PermanentRedirect()
+            .insert_header((LOCATION, location))
+            .finish();
+
+        Box::pin(ready(Ok(request
+            .into_response(response)
+            .map_into_right_body())))
+    }
+}
+
+impl<S> HttpsRedirectMiddleware<S> {
+    fn location(&self, request: &ServiceRequest) -> String {
+        // the Host header, not `connection_info` which honours the forwarded headers
+        // whether the proxy is trusted or not
+        let host = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| request.uri().host())
+            .unwrap_or_else(|| request.app_config().host());
+
+        // without the port, `[::1]:80` or `localhost:80`
+        let host = match host.find(']') {
+            Some(end) if host.starts_with('[') => &host[..=end],
+            _ => host.split(':').next().unwrap_or(host),
+        };
+
+        let path = request
+            .uri()
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+
+        match self.port {
+            443 => format!("https://{}{}", host, path),
+            port => format!("https://{}:{}{}", host, port, path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpsRedirect;
+    use actix_web::{
+        http::{
+            header::{HOST, LOCATION},
+            StatusCode,
+        },
+        test, web, App, HttpResponse,
+    };
+
+    const PEER: &str = "203.0.113.7:51000";
+
+    async fn call(port: u16, request: test::TestRequest) -> (StatusCode, Option<String>) {
+        let app = test::init_service(
+            App::new()
+                .wrap(HttpsRedirect::new(port))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let response = test::call_service(&app, request.to_request()).await;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .map(|location| location.to_str().unwrap().to_owned());
+
+        (response.status(), location)
+    }
+
+    fn request(host: &str) -> test::TestRequest {
+        test::TestRequest::get()
+            .uri("/users/me?locale=fr")
+            .insert_header((HOST, host))
+            .peer_addr(PEER.parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn redirect_without_the_port_of_the_host() {
+        assert_eq!(
+            call(443, request("example.com:80")).await,
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                Some("https://example.com/users/me?locale=fr".to_owned())
+            )
+        );
+        assert_eq!(
+            call(8443, request("[::1]:8080")).await.1,
+            Some("https://[::1]:8443/users/me?locale=fr".to_owned())
+        );
+    }
+
+    #[actix_web::test]
+    async fn redirect_with_a_host_without_port() {
+        assert_eq!(
+            call(443, request("example.com")).await.1,
+            Some("https://example.com/users/me?locale=fr".to_owned())
+        );
+        assert_eq!(
+            call(8443, request("example.com")).await.1,
+            Some("https://example.com:8443/users/me?locale=fr".to_owned())
+        );
+    }
+
+    #[actix_web::test]
+    async fn pass_the_health_checks_and_the_unix_sockets() {
+        let healthz = test::TestRequest::get()
+            .uri("/healthz")
+            .peer_addr(PEER.parse().unwrap());
+        assert_eq!(call(443, healthz).await, (StatusCode::OK, None));
+
+        // no peer address, like the requests of a unix socket
+        let unix = test::TestRequest::get()
+            .uri("/users/me")
+            .insert_header((HOST, "example.com"));
+        assert_eq!(call(443, unix).await, (StatusCode::OK, None));
+    }
+}
@@ -0,0 +1,98 @@
+use actix_session::SessionExt;
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{HeaderValue, ACCEPT_LANGUAGE, CONTENT_LANGUAGE},
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+use crate::{
+    i18n::{self, Locale},
+    utils::session,
+};
+
+/// Picks the locale of the request, from the session of a logged in user,
+/// then the `Accept-Language` header, and makes it the `i18n::current` locale,
+/// must be wrapped inside the `SessionMiddleware`
+#[derive(Debug, Clone, Default)]
+pub struct Localization;
+
+impl<S, B> Transform<S, ServiceRequest> for Localization
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = LocalizationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LocalizationMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct LocalizationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for LocalizationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let locale = Self::locale(&request);
+
+        Box::pin(i18n::scope(locale, async move {
+            let content_language = HeaderValue::from_static(locale.tag());
+
+            // errors of inner middlewares are rendered here, so their messages are localized too,
+            // without keeping a clone of the request, the router needs to be its only owner
+            match service.call(request).await {
+                Ok(mut response) => {
+                    response
+                        .headers_mut()
+                        .insert(CONTENT_LANGUAGE, content_language);
+
+                    Ok(response)
+                }
+                Err(e) => {
+                    let mut response = e.error_response();
+
+                    response
+                        .headers_mut()
+                        .insert(CONTENT_LANGUAGE, content_language);
+
+                    Err(InternalError::from_response(e, response).into())
+                }
+            }
+        }))
+    }
+}
+
+impl<S> LocalizationMiddleware<S> {
+    fn locale(request: &ServiceRequest) -> Locale {
+        if let Ok(Some(locale)) = session::locale(&request.get_session()) {
+            return locale;
+        }
+
+        request
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Locale::negotiate)
+            .unwrap_or_default()
+    }
+}
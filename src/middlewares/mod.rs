@@ -0,0 +1,6 @@
+pub mod https_redirect;
+pub mod locale;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_context;
+pub mod role;
@@ -0,0 +1,400 @@
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::PayloadError,
+    web::{Bytes, Data},
+};
+use async_trait::async_trait;
+use futures::{
+    future::{ready, LocalBoxFuture, Ready},
+    stream::{self, Stream},
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    rc::Rc,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::RateLimitRule,
+    database::redis::Redis,
+    errors::Error::{self, InternalServerError, TooManyRequests},
+    i18n::t,
+    metrics,
+    state::State,
+};
+
+/// Which limits of `RateLimitConfig` apply to the route
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitPolicy {
+    Code,
+    Auth,
+}
+
+/// Counts requests per route and client address, and per route and email
+/// (the `email` or `account` field of the JSON body) in fixed windows stored in redis,
+/// responds with `429 Too Many Requests` and `Retry-After` once a limit is exceeded,
+/// the counters are kept by the `RateLimitStore` registered as app data
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    policy: RateLimitPolicy,
+}
+
+impl RateLimit {
+    pub fn new(policy: RateLimitPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            policy: self.policy,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    policy: RateLimitPolicy,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let policy = self.policy;
+
+        Box::pin(async move {
+            let state = match request.app_data::<Data<State>>() {
+                Some(state) => state.to_owned(),
+                None => {
+                    return Err(InternalServerError(
+                        "State is not registered as app data".to_owned(),
+                    )
+                    .into())
+                }
+            };
+
+            let config = &state.config.rate_limit_config;
+            let rule = match policy {
+                RateLimitPolicy::Code => config.code.to_owned(),
+                RateLimitPolicy::Auth => config.auth.to_owned(),
+            };
+
+            let route = request
+                .match_pattern()
+                .unwrap_or_else(|| request.path().to_owned());
+            let store = match request.app_data::<Data<dyn RateLimitStore>>() {
+                Some(store) => store.to_owned(),
+                None => {
+                    return Err(InternalServerError(
+                        "RateLimitStore is not registered as app data".to_owned(),
+                    )
+                    .into())
+                }
+            };
+
+            if rule.per_ip > 0 {
+                let address = if config.trust_proxy {
+                    request
+                        .connection_info()
+                        .realip_remote_addr()
+                        .map(str::to_owned)
+                } else {
+                    request.peer_addr().map(|address| address.ip().to_string())
+                };
+
+                // requests over a unix socket have no address, they come from a local proxy,
+                // which has to forward the client address for `trust_proxy` to limit them
+                if let Some(address) = address {
+                    let key = format!("rate-limit:{}:ip:{}", route, address);
+
+                    hit(store.as_ref(), key, rule.per_ip, &rule).await?;
+                }
+            }
+
+            if rule.per_email > 0 {
+                // read the body, and put it back for the handler
+                let body = request.extract::<Bytes>().await?;
+
+                if let Some(email) = read_email(&body) {
+                    let key = format!("rate-limit:{}:email:{}", route, email);
+
+                    hit(store.as_ref(), key, rule.per_email, &rule).await?;
+                }
+
+                request.set_payload(into_payload(body));
+            }
+
+            service.call(request).await
+        })
+    }
+}
+
+/// Counters of the requests in fixed windows,
+/// `Redis` in production and `MemoryRateLimitStore` in tests
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Count one request for the key, the window of `window` seconds starts with the first request,
+    /// returns the count and the seconds left in the window
+    async fn hit(&self, key: &str, window: u64) -> Result<(u64, i64), Error>;
+}
+
+#[async_trait]
+impl RateLimitStore for Redis {
+    async fn hit(&self, key: &str, window: u64) -> Result<(u64, i64), Error> {
+        let _timer = metrics::redis_timer("rate_limit");
+
+        let (count, ttl) = redis::pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(key)
+            .arg(0)
+            .arg("EX")
+            .arg(window)
+            .arg("NX")
+            .ignore()
+            .incr(key, 1)
+            .ttl(key)
+            .query_async(&mut self.connection.clone())
+            .await?;
+
+        Ok((count, ttl))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    /// Count and end of the window of each key
+    windows: Mutex<HashMap<String, (u64, Instant)>>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn hit(&self, key: &str, window: u64) -> Result<(u64, i64), Error> {
+        let mut windows = self
+            .windows
+            .lock()
+            .map_err(|_| InternalServerError("Rate limit store is poisoned".to_owned()))?;
+
+        let now = Instant::now();
+        let (count, end) = windows
+            .entry(key.to_owned())
+            .and_modify(|(count, end)| {
+                if *end <= now {
+                    *count = 0;
+                    *end = now + Duration::from_secs(window);
+                }
+            })
+            .or_insert_with(|| (0, now + Duration::from_secs(window)));
+
+        *count += 1;
+
+        Ok((*count, end.saturating_duration_since(now).as_secs() as i64))
+    }
+}
+
+/// Count one request for the key, and fail once there are more than `limit` in the window
+async fn hit(
+    store: &dyn RateLimitStore,
+    key: String,
+    limit: u64,
+    rule: &RateLimitRule,
+) -> Result<(), Error> {
+    let (count, ttl) = store.hit(&key, rule.window).await?;
+
+    if count > limit {
+        return Err(TooManyRequests(
+            t("error.too_many_requests"),
+            ttl.max(1) as u64,
+        ));
+    }
+
+    Ok(())
+}
+
+fn read_email(body: &Bytes) -> Option<String> {
+    let value = serde_json::from_slice::<Value>(body).ok()?;
+    let email = value
+        .get("email")
+        .or_else(|| value.get("account"))?
+        .as_str()?;
+
+    Some(email.trim().to_lowercase())
+}
+
+fn into_payload(body: Bytes) -> Payload {
+    let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+        Box::pin(stream::once(ready(Ok(body))));
+
+    Payload::from(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::Service,
+        http::StatusCode,
+        test,
+        web::{self, Bytes, Data},
+        App, HttpResponse,
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    use super::{read_email, MemoryRateLimitStore, RateLimit, RateLimitPolicy, RateLimitStore};
+    use crate::{
+        config::Config,
+        testing::{self, Fixture},
+    };
+
+    #[actix_web::test]
+    async fn count_in_fixed_windows() {
+        let store = MemoryRateLimitStore::new();
+
+        assert_eq!(store.hit("a", 60).await.unwrap().0, 1);
+        assert_eq!(store.hit("a", 60).await.unwrap().0, 2);
+        assert_eq!(store.hit("b", 60).await.unwrap().0, 1);
+
+        let (count, ttl) = store.hit("a", 60).await.unwrap();
+        assert_eq!(count, 3);
+        assert!(ttl > 0 && ttl <= 60);
+
+        // a window which has passed starts over
+        assert_eq!(store.hit("c", 0).await.unwrap().0, 1);
+        assert_eq!(store.hit("c", 0).await.unwrap().0, 1);
+    }
+
+    #[actix_web::test]
+    async fn read_the_email_or_account() {
+        let email = |value: serde_json::Value| read_email(&Bytes::from(value.to_string()));
+
+        assert_eq!(
+            email(json!({ "email": " Alice@Example.com " })),
+            Some("alice@example.com".to_owned())
+        );
+        assert_eq!(
+            email(json!({ "account": "alice" })),
+            Some("alice".to_owned())
+        );
+        assert_eq!(email(json!({ "username": "alice" })), None);
+        assert_eq!(read_email(&Bytes::from_static(b"email=alice")), None);
+    }
+
+    /// Config of the limits of the code routes, 0 disables one
+    fn config(per_ip: u64, per_email: u64) -> Config {
+        let source = testing::source()
+            .set("rate_limit.code.per_ip", per_ip)
+            .set("rate_limit.code.per_email", per_email);
+
+        Config::from_source(&source).unwrap()
+    }
+
+    macro_rules! app {
+        ($config:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(Data::new(Fixture::with_config($config).state))
+                    .app_data(Data::from(
+                        Arc::new(MemoryRateLimitStore::new()) as Arc<dyn RateLimitStore>
+                    ))
+                    .service(
+                        web::resource("/codes")
+                            .wrap(RateLimit::new(RateLimitPolicy::Code))
+                            // the handler gets the body read by the middleware
+                            .to(|body: Bytes| async move { HttpResponse::Ok().body(body) }),
+                    ),
+            )
+            .await
+        };
+    }
+
+    macro_rules! call {
+        ($app:expr, $request:expr) => {
+            match $app.call($request.to_request()).await {
+                Ok(response) => (response.status(), test::read_body(response).await),
+                Err(error) => (error.as_response_error().status_code(), Bytes::new()),
+            }
+        };
+    }
+
+    fn request(email: &str) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/codes")
+            .set_json(json!({ "email": email }))
+    }
+
+    #[actix_web::test]
+    async fn limit_per_email() {
+        let app = app!(config(0, 1));
+
+        let (status, body) = call!(app, request("alice@example.com"));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "email": "alice@example.com" }).to_string());
+
+        let (status, _) = call!(app, request("ALICE@example.com"));
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        let (status, _) = call!(app, request("bob@example.com"));
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn limit_per_address() {
+        let app = app!(config(1, 0));
+        let alice = "10.0.0.1:1234".parse().unwrap();
+        let bob = "10.0.0.2:1234".parse().unwrap();
+
+        let (status, _) = call!(app, request("alice@example.com").peer_addr(alice));
+        assert_eq!(status, StatusCode::OK);
+
+        // the port does not matter
+        let (status, _) = call!(
+            app,
+            request("bob@example.com").peer_addr("10.0.0.1:5678".parse().unwrap())
+        );
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        let (status, _) = call!(app, request("alice@example.com").peer_addr(bob));
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn requests_without_address_are_not_limited_per_address() {
+        let app = app!(config(1, 0));
+
+        for _ in 0..3 {
+            let (status, _) = call!(app, request("alice@example.com"));
+            assert_eq!(status, StatusCode::OK);
+        }
+    }
+}
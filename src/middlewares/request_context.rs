@@ -0,0 +1,158 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{HeaderName, HeaderValue, ACCEPT},
+    HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::{rc::Rc, time::Instant};
+use tracing::{info, info_span, Instrument};
+use uuid::Uuid;
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static CONTEXT: RequestInfo;
+}
+
+/// What the handling of a request needs to know outside of the extractors,
+/// like the error responses, which have no access to the request
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    /// The `X-Request-Id` of the request if it is valid, a new uuid otherwise
+    pub id: String,
+    /// The client accepts RFC 7807 `application/problem+json` errors
+    pub problem_json: bool,
+}
+
+impl RequestInfo {
+    fn from_request(request: &ServiceRequest) -> Self {
+        let id = request
+            .headers()
+            .get(REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| Self::is_valid_id(id))
+            .map(str::to_owned)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let problem_json = request
+            .headers()
+            .get_all(ACCEPT)
+            .filter_map(|value| value.to_str().ok())
+            .any(|accept| accept.contains("application/problem+json"));
+
+        Self { id, problem_json }
+    }
+
+    /// Ids from clients are echoed in headers and logs, so only short plain ones are kept
+    fn is_valid_id(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }
+}
+
+/// Context of the request being handled, `None` outside of requests
+pub fn current() -> Option<RequestInfo> {
+    CONTEXT.try_with(|context| context.to_owned()).ok()
+}
+
+/// Sets the `RequestInfo` of each request, available through `current()`
+/// and the request extensions, and returns the request id in the `X-Request-Id` header,
+/// the request is handled in a `request` span with its id, so every log line carries it,
+/// should be the outermost middleware so every response carries the id
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestContext
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestContextMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestContextMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestContextMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestContextMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let context = RequestInfo::from_request(&request);
+        let started = Instant::now();
+
+        let span = info_span!(
+            "request",
+            request_id = %context.id,
+            method = %request.method(),
+            path = %request.path(),
+            remote_addr = request.peer_addr().map(|addr| addr.to_string()).unwrap_or_default(),
+        );
+
+        request.extensions_mut().insert(context.to_owned());
+
+        let future = CONTEXT.scope(context.to_owned(), async move {
+            let id = HeaderValue::from_str(&context.id).ok();
+
+            // errors of the inner middlewares are rendered here, so they carry the request id,
+            // without keeping a clone of the request, the router needs to be its only owner
+            let result: Result<ServiceResponse<B>, actix_web::Error> =
+                match service.call(request).await {
+                    Ok(mut response) => {
+                        if let Some(id) = id {
+                            response.headers_mut().insert(REQUEST_ID, id);
+                        }
+
+                        Ok(response)
+                    }
+                    Err(e) => {
+                        let mut response = e.error_response();
+
+                        if let Some(id) = id {
+                            response.headers_mut().insert(REQUEST_ID, id);
+                        }
+
+                        Err(InternalError::from_response(e, response).into())
+                    }
+                };
+
+            let status = match &result {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+
+            info!(
+                status = status.as_u16(),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "request completed"
+            );
+
+            result
+        });
+
+        Box::pin(future.instrument(span))
+    }
+}
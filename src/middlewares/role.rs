@@ -0,0 +1,182 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    FromRequest, HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+use crate::{
+    errors::Error::Forbidden, extractors::users::AuthenticatedUser, i18n::t,
+    models::users::role::Role,
+};
+
+/// Requires a logged in user with at least the given role,
+/// attach it to a `Scope` or `Resource` with `.wrap(RequireRole::new(Role::Admin))`
+#[derive(Debug, Clone)]
+pub struct RequireRole {
+    role: Role,
+}
+
+impl RequireRole {
+    pub fn new(role: Role) -> Self {
+        Self { role }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            role: self.role.to_owned(),
+        }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    role: Role,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let role = self.role.to_owned();
+
+        Box::pin(async move {
+            let user = AuthenticatedUser::extract(request.request()).await?;
+
+            if !user.0.has_role(&role) {
+                return Err(Forbidden(t("error.forbidden")).into());
+            }
+
+            // cache the user, so the handler does not need to load it again
+            request.extensions_mut().insert(user);
+
+            service.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_identity::IdentityMiddleware;
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{
+        cookie::{Cookie, Key},
+        dev::Service,
+        http::StatusCode,
+        test,
+        web::{self, Data, Path},
+        App, HttpRequest, HttpResponse,
+    };
+
+    use super::RequireRole;
+    use crate::{
+        controllers::Response,
+        models::users::{role::Role, User},
+        state::State,
+        testing::Fixture,
+        utils::session,
+    };
+
+    /// Log in as a new user with the role in the path
+    async fn login(role: Path<Role>, state: Data<State>, request: HttpRequest) -> Response {
+        let user = User::new(
+            "alice@example.com".to_owned(),
+            "alice".to_owned(),
+            "Passw0rd".to_owned(),
+            role.into_inner(),
+        );
+
+        state.users.create(&user).await?;
+        session::login(&request, &user)?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    macro_rules! app {
+        () => {
+            test::init_service(
+                App::new()
+                    .app_data(Data::new(Fixture::new().state))
+                    .wrap(IdentityMiddleware::default())
+                    .wrap(SessionMiddleware::new(
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    ))
+                    .route("/login/{role}", web::post().to(login))
+                    .service(
+                        web::resource("/admin")
+                            .wrap(RequireRole::new(Role::Admin))
+                            .to(HttpResponse::Ok),
+                    ),
+            )
+            .await
+        };
+    }
+
+    /// Status of `/admin` for a user with the given role, or without login
+    async fn admin_status(role: Option<&str>) -> StatusCode {
+        let app = app!();
+
+        let mut request = test::TestRequest::get().uri("/admin");
+
+        if let Some(role) = role {
+            let login = test::TestRequest::post()
+                .uri(&format!("/login/{}", role))
+                .to_request();
+            let response = test::call_service(&app, login).await;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            let cookie = response
+                .response()
+                .cookies()
+                .next()
+                .map(Cookie::into_owned)
+                .expect("the session cookie is set");
+            request = request.cookie(cookie);
+        }
+
+        // errors of middlewares become responses outside of the test service
+        match app.call(request.to_request()).await {
+            Ok(response) => response.status(),
+            Err(error) => error.as_response_error().status_code(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn require_login() {
+        assert_eq!(admin_status(None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn reject_lower_roles() {
+        assert_eq!(admin_status(Some("author")).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn accept_the_role_and_higher_ones() {
+        assert_eq!(admin_status(Some("admin")).await, StatusCode::OK);
+        assert_eq!(admin_status(Some("root")).await, StatusCode::OK);
+    }
+}
@@ -1,5 +1,6 @@
 use serde_json::Value;
 
+pub mod outbox;
 pub mod users;
 
 pub trait IntoJson {
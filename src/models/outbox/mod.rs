@@ -0,0 +1,180 @@
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::OutboxConfig,
+    database::{
+        Collection::{self, Outbox},
+        Indexes,
+    },
+    i18n::Locale,
+    models::users::codes::{Code, CodeType},
+};
+
+pub mod repository;
+
+/// An email waiting to be sent by the outbox worker,
+/// the document is removed once the email has been delivered
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMessage {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    to: String,
+    /// Language of the email, the texts of the body are already in it
+    locale: Locale,
+    body: OutboxBody,
+    status: OutboxStatus,
+    /// Count of claims by the worker, including the one in progress
+    attempts: i32,
+    /// The message is not claimed before this time
+    next_attempt_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    created_at: DateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OutboxBody {
+    /// The plaintext is derived from the code id when the email is sent, only its hash is stored
+    #[serde(rename_all = "camelCase")]
+    Code {
+        code_type: CodeType,
+        code_expire: i64,
+        /// The email is dropped if this code is no longer active
+        code_id: ObjectId,
+    },
+    /// A password reset requested for the email, the worker issues the code
+    /// if a user has this email, and drops the message otherwise
+    PasswordResetRequest,
+    Notification {
+        subject: String,
+        header: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OutboxStatus {
+    Pending,
+    /// Failed `max_attempts` times, kept for inspection and never retried
+    DeadLetter,
+}
+
+impl From<OutboxStatus> for Bson {
+    fn from(status: OutboxStatus) -> Self {
+        use OutboxStatus::*;
+
+        match status {
+            Pending => Bson::String("pending".to_owned()),
+            DeadLetter => Bson::String("deadLetter".to_owned()),
+        }
+    }
+}
+
+impl OutboxMessage {
+    /// Email carrying a new plaintext of the given code
+    pub fn code(to: String, locale: Locale, code_expire: i64, saved: &Code) -> Self {
+        let body = OutboxBody::Code {
+            code_type: saved.code_type(),
+            code_expire,
+            code_id: saved.id(),
+        };
+
+        Self::new(to, locale, body)
+    }
+
+    /// Request of a password reset code, queued whether a user has the email or not
+    pub fn password_reset_request(to: String, locale: Locale) -> Self {
+        Self::new(to, locale, OutboxBody::PasswordResetRequest)
+    }
+
+    pub fn notification(
+        to: String,
+        locale: Locale,
+        subject: String,
+        header: String,
+        message: String,
+    ) -> Self {
+        let body = OutboxBody::Notification {
+            subject,
+            header,
+            message,
+        };
+
+        Self::new(to, locale, body)
+    }
+
+    fn new(to: String, locale: Locale, body: OutboxBody) -> Self {
+        Self {
+            id: ObjectId::new(),
+            to,
+            locale,
+            body,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            next_attempt_at: DateTime::now(),
+            last_error: None,
+            created_at: DateTime::now(),
+        }
+    }
+
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    pub fn locale(&self) -> Locale {
+        self.locale
+    }
+
+    pub fn body(&self) -> &OutboxBody {
+        &self.body
+    }
+
+    pub fn code_id(&self) -> Option<ObjectId> {
+        match self.body {
+            OutboxBody::Code { code_id, .. } => Some(code_id),
+            OutboxBody::PasswordResetRequest | OutboxBody::Notification { .. } => None,
+        }
+    }
+
+    /// Whether the attempt in progress is the last one allowed
+    pub fn is_last_attempt(&self, outbox_config: &OutboxConfig) -> bool {
+        self.attempts as u64 >= outbox_config.max_attempts
+    }
+
+    /// Time of the next attempt after a failure, the delay doubles with each attempt
+    pub fn next_attempt(&self, outbox_config: &OutboxConfig) -> DateTime {
+        let exponent = (self.attempts.max(1) - 1).min(32) as u32;
+        let delay = outbox_config
+            .backoff
+            .saturating_mul(2u64.saturating_pow(exponent))
+            .min(outbox_config.max_backoff);
+
+        DateTime::from_millis(DateTime::now().timestamp_millis() + delay as i64 * 1000)
+    }
+}
+
+impl Indexes for OutboxMessage {
+    const COLLECTION: Collection = Outbox;
+
+    fn indexes() -> Vec<IndexModel> {
+        // matches `OutboxRepository::claim`
+        let due = IndexModel::builder()
+            .keys(doc! { "status": 1, "nextAttemptAt": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name("status_nextAttemptAt".to_string())
+                    .build(),
+            )
+            .build();
+
+        vec![due]
+    }
+}
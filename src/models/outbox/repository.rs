@@ -0,0 +1,235 @@
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, from_document, oid::ObjectId, DateTime, Document},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+};
+use std::{collections::HashMap, sync::Mutex};
+use tracing::error;
+
+use crate::{
+    database::{Collection::Outbox, Database},
+    errors::Error::{self, InternalServerError},
+    models::outbox::{OutboxMessage, OutboxStatus},
+};
+
+/// Queue of the emails to send, `MongoOutboxRepository` in production and `MemoryOutboxRepository` in tests
+#[async_trait]
+pub trait OutboxRepository: Send + Sync {
+    async fn enqueue(&self, message: &OutboxMessage) -> Result<(), Error>;
+
+    /// Take the pending message due the earliest and count one attempt,
+    /// the message is hidden from other claims for `lease` seconds,
+    /// so it is retried if the worker stops before settling it,
+    /// messages that can not be read are dead-lettered and skipped
+    async fn claim(&self, lease: i64) -> Result<Option<OutboxMessage>, Error>;
+
+    /// Remove a delivered or dropped message
+    async fn delete(&self, message: &OutboxMessage) -> Result<(), Error>;
+
+    async fn retry(
+        &self,
+        message: &OutboxMessage,
+        next_attempt_at: DateTime,
+        error: String,
+    ) -> Result<(), Error>;
+
+    async fn dead_letter(&self, message: &OutboxMessage, error: String) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MongoOutboxRepository {
+    db: Database,
+}
+
+impl MongoOutboxRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for MongoOutboxRepository {
+    async fn enqueue(&self, message: &OutboxMessage) -> Result<(), Error> {
+        self.db
+            .collection::<OutboxMessage>(Outbox)
+            .insert_one(message, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn claim(&self, lease: i64) -> Result<Option<OutboxMessage>, Error> {
+        let now = DateTime::now();
+        let leased_until = DateTime::from_millis(now.timestamp_millis() + lease * 1000);
+
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "nextAttemptAt": 1 })
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let collection = self.db.collection::<Document>(Outbox);
+
+        loop {
+            let document = match collection
+                .find_one_and_update(
+                    doc! {
+                        "status": OutboxStatus::Pending,
+                        "nextAttemptAt": { "$lte": now }
+                    },
+                    doc! {
+                        "$set": { "nextAttemptAt": leased_until },
+                        "$inc": { "attempts": 1 }
+                    },
+                    options.to_owned(),
+                )
+                .await?
+            {
+                Some(document) => document,
+                None => return Ok(None),
+            };
+
+            // a message the worker can not read would otherwise fail every claim until its lease ends
+            match from_document::<OutboxMessage>(document.to_owned()) {
+                Ok(message) => return Ok(Some(message)),
+                Err(e) => {
+                    error!("Dead-lettering unreadable outbox message: {}", e);
+
+                    collection
+                        .update_one(
+                            doc! { "_id": document.get("_id") },
+                            doc! {
+                                "$set": {
+                                    "status": OutboxStatus::DeadLetter,
+                                    "lastError": e.to_string()
+                                }
+                            },
+                            None,
+                        )
+                        .await?;
+                }
+            }
+        }
+    }
+
+    async fn delete(&self, message: &OutboxMessage) -> Result<(), Error> {
+        self.db
+            .collection::<OutboxMessage>(Outbox)
+            .delete_one(doc! { "_id": message.id }, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn retry(
+        &self,
+        message: &OutboxMessage,
+        next_attempt_at: DateTime,
+        error: String,
+    ) -> Result<(), Error> {
+        self.db
+            .collection::<OutboxMessage>(Outbox)
+            .update_one(
+                doc! { "_id": message.id },
+                doc! { "$set": { "nextAttemptAt": next_attempt_at, "lastError": error } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn dead_letter(&self, message: &OutboxMessage, error: String) -> Result<(), Error> {
+        self.db
+            .collection::<OutboxMessage>(Outbox)
+            .update_one(
+                doc! { "_id": message.id },
+                doc! { "$set": { "status": OutboxStatus::DeadLetter, "lastError": error } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps the queue in memory, dead letters stay in it
+#[derive(Debug, Default)]
+pub struct MemoryOutboxRepository {
+    messages: Mutex<HashMap<ObjectId, OutboxMessage>>,
+}
+
+impl MemoryOutboxRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All messages still in the queue, including the dead letters
+    pub fn messages(&self) -> Result<Vec<OutboxMessage>, Error> {
+        Ok(self.lock()?.values().cloned().collect())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<ObjectId, OutboxMessage>>, Error> {
+        self.messages
+            .lock()
+            .map_err(|_| InternalServerError("Outbox repository is poisoned".to_owned()))
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for MemoryOutboxRepository {
+    async fn enqueue(&self, message: &OutboxMessage) -> Result<(), Error> {
+        self.lock()?.insert(message.id, message.to_owned());
+
+        Ok(())
+    }
+
+    async fn claim(&self, lease: i64) -> Result<Option<OutboxMessage>, Error> {
+        let now = DateTime::now();
+        let mut messages = self.lock()?;
+
+        let option = messages
+            .values_mut()
+            .filter(|message| {
+                message.status == OutboxStatus::Pending && message.next_attempt_at <= now
+            })
+            .min_by_key(|message| message.next_attempt_at)
+            .map(|message| {
+                message.attempts += 1;
+                message.next_attempt_at =
+                    DateTime::from_millis(now.timestamp_millis() + lease * 1000);
+
+                message.to_owned()
+            });
+
+        Ok(option)
+    }
+
+    async fn delete(&self, message: &OutboxMessage) -> Result<(), Error> {
+        self.lock()?.remove(&message.id);
+
+        Ok(())
+    }
+
+    async fn retry(
+        &self,
+        message: &OutboxMessage,
+        next_attempt_at: DateTime,
+        error: String,
+    ) -> Result<(), Error> {
+        if let Some(stored) = self.lock()?.get_mut(&message.id) {
+            stored.next_attempt_at = next_attempt_at;
+            stored.last_error = Some(error);
+        }
+
+        Ok(())
+    }
+
+    async fn dead_letter(&self, message: &OutboxMessage, error: String) -> Result<(), Error> {
+        if let Some(stored) = self.lock()?.get_mut(&message.id) {
+            stored.status = OutboxStatus::DeadLetter;
+            stored.last_error = Some(error);
+        }
+
+        Ok(())
+    }
+}
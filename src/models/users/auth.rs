@@ -9,25 +9,15 @@ use crate::{
 #[derive(Debug, Deserialize, Default, Validate)]
 #[serde(rename_all = "camelCase", default)]
 pub struct Registrar {
-    #[validate(email(message = "Please provide a valid email address"))]
+    #[validate(email(code = "invalid_email"))]
     pub email: String,
-    #[validate(regex(
-        path = "REGEX_USERNAME",
-        message = "The username must be 5-16 characters long and start with a letter, and can only contain letters, numbers, and underscores"
-    ))]
+    #[validate(regex(path = "REGEX_USERNAME", code = "invalid_username"))]
     username: String,
-    #[validate(custom(
-        function = "check_password_strength",
-        message = "The password must be 8-16 characters long and contain at least one uppercase letter, one lowercase letter, and one number"
-    ))]
+    #[validate(custom = "check_password_strength")]
     password: String,
-    #[validate(must_match(other = "password", message = "The passwords do not match"))]
+    #[validate(must_match(other = "password", code = "password_mismatch"))]
     password_confirm: String,
-    #[validate(length(
-        min = 6,
-        max = 6,
-        message = "Invalid registration code, please check your email and try again"
-    ))]
+    #[validate(length(min = 6, max = 6, code = "invalid_registration_code"))]
     pub code: String,
 }
 
@@ -36,3 +26,48 @@ impl Registrar {
         User::new(self.email, self.username, self.password, Role::User)
     }
 }
+
+#[derive(Debug, Deserialize, Default, Validate)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Credentials {
+    /// Either the email or the username of the account
+    #[validate(length(min = 1, code = "account_required"))]
+    pub account: String,
+    #[validate(length(min = 1, code = "password_required"))]
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Default, Validate)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PasswordResetter {
+    #[validate(email(code = "invalid_email"))]
+    pub email: String,
+    #[validate(custom = "check_password_strength")]
+    pub password: String,
+    #[validate(must_match(other = "password", code = "password_mismatch"))]
+    password_confirm: String,
+    #[validate(length(min = 6, max = 6, code = "invalid_password_reset_code"))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Default, Validate)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PasswordChanger {
+    #[validate(length(min = 1, code = "current_password_required"))]
+    pub current_password: String,
+    #[validate(custom = "check_password_strength")]
+    pub password: String,
+    #[validate(must_match(other = "password", code = "password_mismatch"))]
+    password_confirm: String,
+    /// Log out every other session of the user as well
+    pub end_other_sessions: bool,
+}
+
+#[derive(Debug, Deserialize, Default, Validate)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EmailChanger {
+    #[validate(email(code = "invalid_email"))]
+    pub email: String,
+    #[validate(length(min = 6, max = 6, code = "invalid_verification_code"))]
+    pub code: String,
+}
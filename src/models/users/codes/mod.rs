@@ -0,0 +1,554 @@
+use futures::TryStreamExt;
+use hmac::{Hmac, Mac};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    options::IndexOptions,
+    IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    fmt::{self, Display, Formatter, Write},
+    time::Duration,
+};
+use subtle::ConstantTimeEq;
+use tracing::info;
+
+use crate::{
+    config::Config,
+    database::{
+        Collection::{self, Codes},
+        Database, Indexes,
+    },
+    errors::Error::{self, BadRequest, CodeLocked},
+    i18n::{t, t_args, Locale},
+    models::{
+        outbox::{repository::OutboxRepository, OutboxMessage},
+        users::codes::repository::CodeRepository,
+    },
+};
+
+pub mod repository;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Code {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    email: String,
+    /// The user changing their email to `email`, only they can use the code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<ObjectId>,
+    /// Hex encoded HMAC-SHA256 of the code, the code itself is only sent by email
+    #[serde(default)]
+    code_hash: String,
+    code_type: CodeType,
+    active: bool,
+    /// Count of wrong candidates checked against the code
+    #[serde(default)]
+    attempts: i32,
+    created_at: DateTime,
+    expired_at: DateTime,
+}
+
+impl Code {
+    /// Create a new code instance, with a valid time in minutes, unit is minute,
+    /// its plaintext is derived from its id by `Code::plaintext` when the email is sent
+    pub fn new(
+        email: String,
+        user_id: Option<ObjectId>,
+        code_type: CodeType,
+        code_expire: i64,
+        secret: &str,
+    ) -> Self {
+        let now = DateTime::now().timestamp_millis();
+        let expired_at = now + code_expire * 60 * 1000;
+        let id = ObjectId::new();
+
+        Self {
+            id,
+            email,
+            user_id,
+            code_hash: Self::hash(&Self::plaintext(id, secret), secret),
+            code_type,
+            active: true,
+            attempts: 0,
+            created_at: DateTime::now(),
+            // 默认当前时间加上15分钟
+            expired_at: DateTime::from_millis(expired_at),
+        }
+    }
+
+    pub fn id(&self) -> ObjectId {
+        self.id
+    }
+
+    pub fn code_type(&self) -> CodeType {
+        self.code_type
+    }
+
+    /// Neither used, locked nor expired
+    pub fn is_active(&self) -> bool {
+        self.active && !self.is_expired()
+    }
+
+    /// Deactivated by `max_attempts` wrong candidates, a used code has fewer attempts
+    pub fn is_locked(&self, max_attempts: i32) -> bool {
+        !self.active && self.attempts >= max_attempts && !self.is_expired()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        DateTime::now() > self.expired_at
+    }
+
+    pub fn is_valid(&self, candidate: String, secret: &str) -> bool {
+        let candidate_hash = Self::hash(&candidate, secret);
+
+        // compare in constant time, so the hash can not be guessed byte by byte
+        let matches: bool = candidate_hash
+            .as_bytes()
+            .ct_eq(self.code_hash.as_bytes())
+            .into();
+
+        self.active && !self.is_expired() && matches
+    }
+
+    /// Plaintext of the code with the given id, 6 digits,
+    /// derived from the id so every attempt at sending the email sends the same code
+    pub fn plaintext(id: ObjectId, secret: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC can take key of any size");
+
+        // not the same input as `hash`, so the stored hash tells nothing about the plaintext
+        mac.update(b"plaintext:");
+        mac.update(&id.bytes());
+
+        let bytes = mac.finalize().into_bytes();
+        let number = u64::from_be_bytes(
+            bytes[..8]
+                .try_into()
+                .expect("HMAC-SHA256 is longer than 8 bytes"),
+        );
+
+        (100000 + number % 900000).to_string()
+    }
+
+    pub fn hash(code: &str, secret: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC can take key of any size");
+
+        mac.update(code.as_bytes());
+
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            })
+    }
+
+    /// Replace the plaintext `code` of the codes created before hashing with `codeHash`,
+    /// inactive codes are never checked again, so only their plaintext is removed
+    pub async fn hash_plaintext_codes(secret: &str, db: &Database) -> Result<(), Error> {
+        let collection = db.collection::<Document>(Codes);
+        let filter = doc! { "code": { "$exists": true }, "active": true };
+
+        let documents = collection
+            .find(filter, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        for document in documents.iter() {
+            if let (Ok(id), Ok(code)) = (document.get_object_id("_id"), document.get_str("code")) {
+                collection
+                    .update_one(
+                        doc! { "_id": id },
+                        doc! {
+                            "$set": { "codeHash": Self::hash(code, secret) },
+                            "$unset": { "code": "" },
+                        },
+                        None,
+                    )
+                    .await?;
+            }
+        }
+
+        collection
+            .update_many(
+                doc! { "code": { "$exists": true } },
+                doc! { "$unset": { "code": "" } },
+                None,
+            )
+            .await?;
+
+        if !documents.is_empty() {
+            info!("Hashed {} plaintext codes", documents.len());
+        }
+
+        Ok(())
+    }
+
+    /// Save a new code of the given type and queue the email sending it,
+    /// `user_id` is the user changing their email, the pending codes of other users do not matter
+    pub async fn issue(
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+        locale: Locale,
+        config: &Config,
+        codes: &dyn CodeRepository,
+        outbox: &dyn OutboxRepository,
+    ) -> Result<(), Error> {
+        // check if code of this type for this email already exists
+        if let Some(code) = codes
+            .find_one_by_email(email.to_owned(), code_type, user_id)
+            .await?
+        {
+            // if code is not expired, return error
+            if !code.is_expired() {
+                return Err(BadRequest(t_args(
+                    "code.exists",
+                    &[("code_type", &code_type.to_string()), ("email", &email)],
+                )));
+            } else {
+                // if code is expired, deactivate it
+                codes.deactivate(&code).await?;
+            }
+        }
+
+        // create new code instance, the plaintext is derived by the worker sending it,
+        // so it is never stored
+        let code = Self::new(
+            email.to_owned(),
+            user_id,
+            code_type,
+            config.code_expire,
+            &config.code_secret,
+        );
+
+        // save code to database before queueing the email,
+        // the worker drops the email if the code is no longer active
+        codes.create(&code).await?;
+
+        let message = OutboxMessage::code(email, locale, config.code_expire, &code);
+
+        // without an email the code could never be used, and would block new codes until expired
+        if let Err(e) = outbox.enqueue(&message).await {
+            codes.deactivate(&code).await?;
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Check the candidate against the active code of the email, and of the user for email changes,
+    /// the code is deactivated once it has been used, or after `code_max_attempts` wrong candidates
+    pub async fn consume(
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+        candidate: String,
+        config: &Config,
+        codes: &dyn CodeRepository,
+    ) -> Result<bool, Error> {
+        let locked = || {
+            CodeLocked(t_args(
+                "code.locked",
+                &[("code_type", &code_type.to_string())],
+            ))
+        };
+
+        match codes
+            .find_one_by_email(email.to_owned(), code_type, user_id)
+            .await?
+        {
+            // `false` if a concurrent request has used it first
+            Some(code) if code.is_valid(candidate, &config.code_secret) => {
+                codes.deactivate(&code).await
+            }
+            Some(code) if !code.is_expired() => {
+                if codes
+                    .record_attempt(&code, config.code_max_attempts)
+                    .await?
+                {
+                    return Err(locked());
+                }
+
+                Ok(false)
+            }
+            Some(_) => Ok(false),
+            // the code keeps being reported as locked until a new one is requested
+            None => match codes
+                .find_latest_by_email(email, code_type, user_id)
+                .await?
+            {
+                Some(code) if code.is_locked(config.code_max_attempts) => Err(locked()),
+                _ => Ok(false),
+            },
+        }
+    }
+}
+
+impl Indexes for Code {
+    const COLLECTION: Collection = Codes;
+
+    fn indexes() -> Vec<IndexModel> {
+        // mongodb removes the code once `expiredAt` has passed
+        let ttl = IndexModel::builder()
+            .keys(doc! { "expiredAt": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name("expiredAt".to_string())
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+
+        // matches `CodeRepository::find_one_by_email`
+        let lookup = IndexModel::builder()
+            .keys(doc! { "email": 1, "codeType": 1, "active": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name("email_codeType_active".to_string())
+                    .build(),
+            )
+            .build();
+
+        vec![ttl, lookup]
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CodeType {
+    Registration,
+    PasswordReset,
+    EmailChange,
+}
+
+impl From<CodeType> for Bson {
+    fn from(code_type: CodeType) -> Self {
+        use CodeType::*;
+
+        match code_type {
+            Registration => Bson::String("registration".to_owned()),
+            PasswordReset => Bson::String("passwordReset".to_owned()),
+            EmailChange => Bson::String("emailChange".to_owned()),
+        }
+    }
+}
+
+impl Display for CodeType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use CodeType::*;
+
+        match self {
+            Registration => write!(f, "{}", t("code_type.registration")),
+            PasswordReset => write!(f, "{}", t("code_type.password_reset")),
+            EmailChange => write!(f, "{}", t("code_type.email_change")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use mongodb::bson::oid::ObjectId;
+
+    use super::{
+        repository::{CodeRepository, MemoryCodeRepository},
+        Code, CodeType,
+    };
+    use crate::{
+        config::Config,
+        errors::Error::{self, CodeLocked},
+        testing,
+    };
+
+    const SECRET: &str = "secret";
+    const EMAIL: &str = "alice@example.com";
+
+    #[test]
+    fn plaintext_is_six_digits_derived_from_the_id() {
+        let id = ObjectId::new();
+        let plaintext = Code::plaintext(id, SECRET);
+
+        assert_eq!(plaintext.len(), 6);
+        assert!(plaintext.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(Code::plaintext(id, SECRET), plaintext);
+        assert_ne!(Code::plaintext(id, "other secret"), plaintext);
+    }
+
+    #[test]
+    fn new_code_matches_its_plaintext_only() {
+        let code = Code::new(
+            "alice@example.com".to_owned(),
+            None,
+            CodeType::Registration,
+            15,
+            SECRET,
+        );
+        let plaintext = Code::plaintext(code.id(), SECRET);
+        let wrong = if plaintext == "100000" {
+            "100001"
+        } else {
+            "100000"
+        };
+
+        assert!(code.is_valid(plaintext, SECRET));
+        assert!(!code.is_valid(wrong.to_owned(), SECRET));
+    }
+
+    /// Config allowing 3 attempts, and a saved code of it
+    async fn issued(codes: &dyn CodeRepository) -> (Config, Code) {
+        let config = Config::from_source(&testing::source().set("code.max_attempts", 3)).unwrap();
+        let code = Code::new(
+            EMAIL.to_owned(),
+            None,
+            CodeType::PasswordReset,
+            config.code_expire,
+            &config.code_secret,
+        );
+        codes.create(&code).await.unwrap();
+
+        (config, code)
+    }
+
+    async fn consume(
+        candidate: &str,
+        config: &Config,
+        codes: &dyn CodeRepository,
+    ) -> Result<bool, Error> {
+        Code::consume(
+            EMAIL.to_owned(),
+            CodeType::PasswordReset,
+            None,
+            candidate.to_owned(),
+            config,
+            codes,
+        )
+        .await
+    }
+
+    fn wrong(code: &Code, config: &Config) -> String {
+        match Code::plaintext(code.id(), &config.code_secret).as_str() {
+            "100000" => "100001".to_owned(),
+            _ => "100000".to_owned(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn consume_the_code_once() {
+        let codes = MemoryCodeRepository::new();
+        let (config, code) = issued(&codes).await;
+        let plaintext = Code::plaintext(code.id(), &config.code_secret);
+
+        assert!(consume(&plaintext, &config, &codes).await.unwrap());
+        assert!(!consume(&plaintext, &config, &codes).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn lock_after_max_attempts() {
+        let codes = MemoryCodeRepository::new();
+        let (config, code) = issued(&codes).await;
+        let wrong = wrong(&code, &config);
+
+        assert!(!consume(&wrong, &config, &codes).await.unwrap());
+        assert!(!consume(&wrong, &config, &codes).await.unwrap());
+        assert!(matches!(
+            consume(&wrong, &config, &codes).await,
+            Err(CodeLocked(_))
+        ));
+
+        let stored = codes.find_one_by_id(code.id()).await.unwrap().unwrap();
+        assert!(stored.is_locked(config.code_max_attempts));
+    }
+
+    #[actix_web::test]
+    async fn report_the_lock_until_a_new_code_is_issued() {
+        let codes = MemoryCodeRepository::new();
+        let (config, code) = issued(&codes).await;
+        let wrong = wrong(&code, &config);
+        let plaintext = Code::plaintext(code.id(), &config.code_secret);
+
+        for _ in 0..config.code_max_attempts {
+            let _ = consume(&wrong, &config, &codes).await;
+        }
+
+        // even the correct code is refused once locked
+        assert!(matches!(
+            consume(&plaintext, &config, &codes).await,
+            Err(CodeLocked(_))
+        ));
+        assert!(matches!(
+            consume(&wrong, &config, &codes).await,
+            Err(CodeLocked(_))
+        ));
+
+        let (_, new_code) = issued(&codes).await;
+        let plaintext = Code::plaintext(new_code.id(), &config.code_secret);
+
+        assert!(consume(&plaintext, &config, &codes).await.unwrap());
+    }
+
+    /// Finds the code as it was before a concurrent request used it
+    struct Stale {
+        code: Code,
+        codes: MemoryCodeRepository,
+    }
+
+    #[async_trait]
+    impl CodeRepository for Stale {
+        async fn create(&self, code: &Code) -> Result<(), Error> {
+            self.codes.create(code).await
+        }
+
+        async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<Code>, Error> {
+            self.codes.find_one_by_id(id).await
+        }
+
+        async fn find_one_by_email(
+            &self,
+            _email: String,
+            _code_type: CodeType,
+            _user_id: Option<ObjectId>,
+        ) -> Result<Option<Code>, Error> {
+            Ok(Some(self.code.to_owned()))
+        }
+
+        async fn find_latest_by_email(
+            &self,
+            email: String,
+            code_type: CodeType,
+            user_id: Option<ObjectId>,
+        ) -> Result<Option<Code>, Error> {
+            self.codes
+                .find_latest_by_email(email, code_type, user_id)
+                .await
+        }
+
+        async fn deactivate(&self, code: &Code) -> Result<bool, Error> {
+            self.codes.deactivate(code).await
+        }
+
+        async fn record_attempt(&self, code: &Code, max_attempts: i32) -> Result<bool, Error> {
+            self.codes.record_attempt(code, max_attempts).await
+        }
+    }
+
+    #[actix_web::test]
+    async fn only_one_of_concurrent_requests_consumes_the_code() {
+        let codes = MemoryCodeRepository::new();
+        let (config, code) = issued(&codes).await;
+        let plaintext = Code::plaintext(code.id(), &config.code_secret);
+
+        // both requests found the active code, the first one deactivates it
+        assert!(codes.deactivate(&code).await.unwrap());
+
+        let stale = Stale { code, codes };
+        assert!(!consume(&plaintext, &config, &stale).await.unwrap());
+    }
+}
@@ -0,0 +1,268 @@
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument},
+};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    database::{Collection::Codes, Database},
+    errors::Error::{self, InternalServerError},
+    metrics,
+    models::users::codes::{Code, CodeType},
+};
+
+/// Storage of codes, `MongoCodeRepository` in production and `MemoryCodeRepository` in tests
+#[async_trait]
+pub trait CodeRepository: Send + Sync {
+    async fn create(&self, code: &Code) -> Result<(), Error>;
+
+    async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<Code>, Error>;
+
+    /// Find one code instance by email
+    /// code_type is the type of code, like registration, etc.
+    /// active means the code is not used or expired
+    /// user_id is the user who asked for an email change code, `None` for the other types
+    async fn find_one_by_email(
+        &self,
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+    ) -> Result<Option<Code>, Error>;
+
+    /// Find the latest code by email, active or not, to tell why there is no active one
+    async fn find_latest_by_email(
+        &self,
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+    ) -> Result<Option<Code>, Error>;
+
+    /// Deactivate the code if it is still active, returns whether it was,
+    /// so of concurrent requests using the same code only one succeeds
+    async fn deactivate(&self, code: &Code) -> Result<bool, Error>;
+
+    /// Count one wrong attempt and deactivate the code once `max_attempts` is reached,
+    /// returns whether the code has been locked by it
+    async fn record_attempt(&self, code: &Code, max_attempts: i32) -> Result<bool, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MongoCodeRepository {
+    db: Database,
+}
+
+impl MongoCodeRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl CodeRepository for MongoCodeRepository {
+    async fn create(&self, code: &Code) -> Result<(), Error> {
+        let _timer = metrics::mongo_timer("codes", "create");
+
+        self.db
+            .collection::<Code>(Codes)
+            .insert_one(code, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<Code>, Error> {
+        let _timer = metrics::mongo_timer("codes", "find_one_by_id");
+
+        let option = self
+            .db
+            .collection::<Code>(Codes)
+            .find_one(doc! { "_id": id }, None)
+            .await?;
+
+        Ok(option)
+    }
+
+    async fn find_one_by_email(
+        &self,
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+    ) -> Result<Option<Code>, Error> {
+        let _timer = metrics::mongo_timer("codes", "find_one_by_email");
+
+        let option = self
+            .db
+            .collection::<Code>(Codes)
+            .find_one(
+                doc! {
+                    "email": email,
+                    "codeType": code_type,
+                    // `null` also matches the codes without `userId`
+                    "userId": user_id,
+                    "active": true
+                },
+                None,
+            )
+            .await?;
+
+        Ok(option)
+    }
+
+    async fn find_latest_by_email(
+        &self,
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+    ) -> Result<Option<Code>, Error> {
+        let _timer = metrics::mongo_timer("codes", "find_latest_by_email");
+
+        let options = FindOneOptions::builder().sort(doc! { "_id": -1 }).build();
+
+        let option = self
+            .db
+            .collection::<Code>(Codes)
+            .find_one(
+                doc! {
+                    "email": email,
+                    "codeType": code_type,
+                    "userId": user_id,
+                },
+                options,
+            )
+            .await?;
+
+        Ok(option)
+    }
+
+    async fn deactivate(&self, code: &Code) -> Result<bool, Error> {
+        let _timer = metrics::mongo_timer("codes", "deactivate");
+
+        let result = self
+            .db
+            .collection::<Code>(Codes)
+            .update_one(
+                doc! { "_id": code.id, "active": true },
+                doc! { "$set": { "active": false } },
+                None,
+            )
+            .await?;
+
+        Ok(result.modified_count == 1)
+    }
+
+    async fn record_attempt(&self, code: &Code, max_attempts: i32) -> Result<bool, Error> {
+        let _timer = metrics::mongo_timer("codes", "record_attempt");
+
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        // increase and lock in one update, so concurrent attempts can not exceed the limit
+        let pipeline = vec![
+            doc! { "$set": { "attempts": { "$add": [{ "$ifNull": ["$attempts", 0] }, 1] } } },
+            doc! { "$set": { "active": { "$and": ["$active", { "$lt": ["$attempts", max_attempts] }] } } },
+        ];
+
+        let option = self
+            .db
+            .collection::<Code>(Codes)
+            .find_one_and_update(doc! { "_id": code.id, "active": true }, pipeline, options)
+            .await?;
+
+        // `None` if the code has already been deactivated by another request
+        Ok(matches!(option, Some(code) if !code.active))
+    }
+}
+
+/// Keeps codes in memory, expired codes are not removed
+#[derive(Debug, Default)]
+pub struct MemoryCodeRepository {
+    codes: Mutex<HashMap<ObjectId, Code>>,
+}
+
+impl MemoryCodeRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<ObjectId, Code>>, Error> {
+        self.codes
+            .lock()
+            .map_err(|_| InternalServerError("Code repository is poisoned".to_owned()))
+    }
+}
+
+#[async_trait]
+impl CodeRepository for MemoryCodeRepository {
+    async fn create(&self, code: &Code) -> Result<(), Error> {
+        self.lock()?.insert(code.id, code.to_owned());
+
+        Ok(())
+    }
+
+    async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<Code>, Error> {
+        Ok(self.lock()?.get(&id).cloned())
+    }
+
+    async fn find_one_by_email(
+        &self,
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+    ) -> Result<Option<Code>, Error> {
+        let option = self
+            .lock()?
+            .values()
+            .find(|code| {
+                code.email == email
+                    && code.code_type == code_type
+                    && code.user_id == user_id
+                    && code.active
+            })
+            .cloned();
+
+        Ok(option)
+    }
+
+    async fn find_latest_by_email(
+        &self,
+        email: String,
+        code_type: CodeType,
+        user_id: Option<ObjectId>,
+    ) -> Result<Option<Code>, Error> {
+        let option = self
+            .lock()?
+            .values()
+            .filter(|code| {
+                code.email == email && code.code_type == code_type && code.user_id == user_id
+            })
+            .max_by_key(|code| code.id)
+            .cloned();
+
+        Ok(option)
+    }
+
+    async fn deactivate(&self, code: &Code) -> Result<bool, Error> {
+        match self.lock()?.get_mut(&code.id) {
+            Some(stored) if stored.active => {
+                stored.active = false;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn record_attempt(&self, code: &Code, max_attempts: i32) -> Result<bool, Error> {
+        match self.lock()?.get_mut(&code.id) {
+            Some(stored) if stored.active => {
+                stored.attempts += 1;
+                stored.active = stored.attempts < max_attempts;
+
+                Ok(!stored.active)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -4,6 +4,6 @@ use validator::Validate;
 #[derive(Debug, Deserialize, Default, Validate)]
 #[serde(default)]
 pub struct MailValidator {
-    #[validate(email(message = "Please provide a valid email address"))]
+    #[validate(email(code = "invalid_email"))]
     pub email: String,
 }
@@ -2,20 +2,38 @@ use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
-use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use lazy_static::lazy_static;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::IndexOptions,
+    IndexModel,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use crate::{
-    database::{Collection::Users, Database},
+    database::{
+        Collection::{self, Users},
+        Indexes,
+    },
     errors::Error,
-    models::IntoJson,
+    i18n::{self, Locale},
+    metrics,
+    models::{users::repository::UserRepository, IntoJson},
 };
 
 pub mod auth;
 pub mod codes;
 pub mod mail_validator;
+pub mod query;
+pub mod repository;
 pub mod role;
+pub mod updater;
+
+lazy_static! {
+    /// Verified when there is no user, so the response takes as long as for a wrong password
+    static ref DUMMY_PASSWORD_HASH: String = User::hash_password(ObjectId::new().to_hex());
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -29,6 +47,12 @@ pub struct User {
     role: role::Role,
     created_at: DateTime,
     updated_at: DateTime,
+    /// Sessions logged in before this time are ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sessions_revoked_at: Option<DateTime>,
+    /// Language of the emails and messages, the one of the request when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locale: Option<Locale>,
 }
 
 impl User {
@@ -41,10 +65,14 @@ impl User {
             role,
             created_at: DateTime::now(),
             updated_at: DateTime::now(),
+            sessions_revoked_at: None,
+            locale: Some(i18n::current()),
         }
     }
 
     fn hash_password(password: String) -> String {
+        let _timer = metrics::argon2_timer("hash");
+
         let salt = SaltString::generate(&mut OsRng);
 
         // Argon2 with default params (Argon2id v19)
@@ -58,28 +86,99 @@ impl User {
     }
 
     pub fn verify_password(&self, candidate_password: String) -> bool {
+        Self::verify(&self.password, candidate_password)
+    }
+
+    /// Do the work of `verify_password` for an account which does not exist, always `false`
+    pub fn verify_dummy_password(candidate_password: String) -> bool {
+        Self::verify(&DUMMY_PASSWORD_HASH, candidate_password);
+
+        false
+    }
+
+    fn verify(password: &str, candidate_password: String) -> bool {
+        let _timer = metrics::argon2_timer("verify");
+
         let argon2 = Argon2::default();
 
-        let password_hash = PasswordHash::new(self.password.as_str()).unwrap();
+        let password_hash = PasswordHash::new(password).unwrap();
 
         argon2
             .verify_password(candidate_password.as_ref(), &password_hash)
             .is_ok()
     }
 
-    pub async fn create(user: &Self, db: &Database) -> Result<(), Error> {
-        db.collection::<Self>(Users).insert_one(user, None).await?;
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    pub fn preferred_locale(&self) -> Option<Locale> {
+        self.locale
+    }
+
+    /// Locale of the emails sent to the user
+    pub fn locale(&self) -> Locale {
+        self.locale.unwrap_or_else(i18n::current)
+    }
+
+    /// Whether the user has the given role or a more privileged one
+    pub fn has_role(&self, role: &role::Role) -> bool {
+        self.role >= *role
+    }
 
-        Ok(())
+    /// Users can manage themselves, admins can manage users whose role is lower than theirs,
+    /// so admins are only managed by root
+    pub fn can_manage(&self, other: &Self) -> bool {
+        self.id == other.id || (self.has_role(&role::Role::Admin) && self.role > other.role)
+    }
+
+    /// Whether a session logged in at the given time, unit is millisecond, has been ended
+    pub fn is_session_revoked(&self, logged_in_at: Option<i64>) -> bool {
+        match (self.sessions_revoked_at, logged_in_at) {
+            (Some(revoked_at), Some(logged_in_at)) => logged_in_at < revoked_at.timestamp_millis(),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
     }
 
-    pub async fn find_one_by_email(email: String, db: &Database) -> Result<Option<Self>, Error> {
-        let option = db
-            .collection::<Self>(Users)
-            .find_one(doc! { "email": email }, None)
-            .await?;
+    /// Hash and save the new password, ending all existing sessions if `revoke_sessions` is set
+    pub async fn update_password(
+        &mut self,
+        password: String,
+        revoke_sessions: bool,
+        users: &dyn UserRepository,
+    ) -> Result<(), Error> {
+        let now = DateTime::now();
+
+        self.password = Self::hash_password(password);
+        self.updated_at = now;
+
+        if revoke_sessions {
+            self.sessions_revoked_at = Some(now);
+        }
+
+        users.update_password(self).await
+    }
+}
 
-        Ok(option)
+impl Indexes for User {
+    const COLLECTION: Collection = Users;
+
+    fn indexes() -> Vec<IndexModel> {
+        ["email", "username"]
+            .into_iter()
+            .map(|key| {
+                let options = IndexOptions::builder()
+                    .name(key.to_string())
+                    .unique(true)
+                    .build();
+
+                IndexModel::builder()
+                    .keys(doc! { key: 1 })
+                    .options(options)
+                    .build()
+            })
+            .collect()
     }
 }
 
@@ -93,6 +192,7 @@ impl IntoJson for User {
             "email": self.email,
             "username": self.username,
             "role": self.role,
+            "locale": self.locale,
             "createdAt": created_at,
             "updatedAt": updated_at,
         })
@@ -0,0 +1,229 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    errors::Error::{self, BadRequest},
+    i18n::t_args,
+    models::users::role::Role,
+};
+
+/// Query parameters of the user listing,
+/// either `page` or `cursor` is used for pagination, `cursor` takes precedence
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserQuery {
+    /// Deeper pages are reached with the cursor, skipping is slow and could overflow
+    #[validate(range(min = 1, max = 10000, code = "invalid_page"))]
+    pub page: u64,
+    #[validate(range(min = 1, max = 100, code = "invalid_limit"))]
+    pub limit: i64,
+    /// The `nextCursor` returned by the previous request
+    pub cursor: Option<String>,
+    pub role: Option<Role>,
+    /// Case insensitive substring of the email or username
+    pub search: Option<String>,
+    /// RFC 3339 date time, inclusive
+    pub created_from: Option<String>,
+    /// RFC 3339 date time, exclusive
+    pub created_to: Option<String>,
+    pub sort: SortOrder,
+}
+
+impl Default for UserQuery {
+    fn default() -> Self {
+        Self {
+            page: 1,
+            limit: 20,
+            cursor: None,
+            role: None,
+            search: None,
+            created_from: None,
+            created_to: None,
+            sort: SortOrder::default(),
+        }
+    }
+}
+
+impl UserQuery {
+    /// Filter matching every user of the listing, regardless of pagination
+    pub fn filter(&self) -> Result<Document, Error> {
+        let mut filter = doc! {};
+
+        if let Some(role) = self.role.to_owned() {
+            filter.insert("role", role);
+        }
+
+        if let Some(search) = self.search.as_ref().filter(|search| !search.is_empty()) {
+            let pattern = doc! { "$regex": regex::escape(search), "$options": "i" };
+
+            filter.insert(
+                "$or",
+                vec![
+                    doc! { "email": pattern.to_owned() },
+                    doc! { "username": pattern },
+                ],
+            );
+        }
+
+        let mut created_at = doc! {};
+        let (from, to) = self.created_range()?;
+
+        if let Some(from) = from {
+            created_at.insert("$gte", from);
+        }
+
+        if let Some(to) = to {
+            created_at.insert("$lt", to);
+        }
+
+        if !created_at.is_empty() {
+            filter.insert("createdAt", created_at);
+        }
+
+        Ok(filter)
+    }
+
+    /// Users before the page, when there is no cursor
+    pub fn skip(&self) -> u64 {
+        self.page
+            .saturating_sub(1)
+            .saturating_mul(self.limit.max(0) as u64)
+    }
+
+    pub fn cursor(&self) -> Result<Option<ObjectId>, Error> {
+        match &self.cursor {
+            Some(cursor) => match ObjectId::parse_str(cursor) {
+                Ok(cursor) => Ok(Some(cursor)),
+                Err(_) => Err(BadRequest(t_args(
+                    "query.invalid_cursor",
+                    &[("cursor", cursor)],
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// The inclusive start and exclusive end of the creation time
+    pub fn created_range(&self) -> Result<(Option<DateTime>, Option<DateTime>), Error> {
+        let from = match &self.created_from {
+            Some(from) => Some(Self::parse_date_time(from)?),
+            None => None,
+        };
+
+        let to = match &self.created_to {
+            Some(to) => Some(Self::parse_date_time(to)?),
+            None => None,
+        };
+
+        Ok((from, to))
+    }
+
+    fn parse_date_time(value: &str) -> Result<DateTime, Error> {
+        DateTime::parse_rfc3339_str(value)
+            .map_err(|_| BadRequest(t_args("query.invalid_date_time", &[("value", value)])))
+    }
+}
+
+/// Users are sorted by creation, newest first by default
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn direction(&self) -> i32 {
+        match self {
+            Self::Asc => 1,
+            Self::Desc => -1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, oid::ObjectId};
+    use serde_json::{json, Value};
+    use validator::Validate;
+
+    use super::UserQuery;
+    use crate::models::users::role::Role;
+
+    fn query(value: Value) -> UserQuery {
+        serde_json::from_value(value).expect("the query is well formed")
+    }
+
+    #[test]
+    fn skip_the_pages_before() {
+        assert_eq!(UserQuery::default().skip(), 0);
+        assert_eq!(query(json!({ "page": 3, "limit": 20 })).skip(), 40);
+    }
+
+    #[test]
+    fn skip_saturates_instead_of_overflowing() {
+        let query = UserQuery {
+            page: u64::MAX,
+            limit: i64::MAX,
+            ..UserQuery::default()
+        };
+
+        assert_eq!(query.skip(), u64::MAX);
+    }
+
+    #[test]
+    fn validate_page_and_limit() {
+        assert!(UserQuery::default().validate().is_ok());
+        assert!(query(json!({ "page": 0 })).validate().is_err());
+        assert!(query(json!({ "page": 10001 })).validate().is_err());
+        assert!(query(json!({ "limit": 0 })).validate().is_err());
+        assert!(query(json!({ "limit": 101 })).validate().is_err());
+    }
+
+    #[test]
+    fn filter_by_role_search_and_creation() {
+        let filter = query(json!({
+            "role": "admin",
+            "search": "a.b",
+            "createdFrom": "2023-01-01T00:00:00Z",
+        }))
+        .filter()
+        .unwrap();
+
+        assert_eq!(filter.get("role"), Some(&Role::Admin.into()));
+
+        // the search is matched literally
+        let pattern = doc! { "$regex": "a\\.b", "$options": "i" };
+        assert_eq!(
+            filter.get_array("$or").unwrap(),
+            &vec![
+                doc! { "email": pattern.to_owned() }.into(),
+                doc! { "username": pattern }.into(),
+            ]
+        );
+
+        let created_at = filter.get_document("createdAt").unwrap();
+        assert!(created_at.contains_key("$gte"));
+        assert!(!created_at.contains_key("$lt"));
+    }
+
+    #[test]
+    fn empty_query_matches_every_user() {
+        assert!(UserQuery::default().filter().unwrap().is_empty());
+        assert!(query(json!({ "search": "" })).filter().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_dates_and_cursors() {
+        assert!(query(json!({ "createdTo": "yesterday" })).filter().is_err());
+        assert!(query(json!({ "cursor": "nope" })).cursor().is_err());
+
+        let id = ObjectId::new();
+        assert_eq!(
+            query(json!({ "cursor": id.to_hex() })).cursor().unwrap(),
+            Some(id)
+        );
+    }
+}
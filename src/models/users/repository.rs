@@ -0,0 +1,535 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    database::{Collection::Users, Database},
+    errors::Error::{self, Duplicate, InternalServerError, NotFound},
+    i18n::{t_args, Locale},
+    metrics,
+    models::users::{query::UserQuery, User},
+};
+
+/// Storage of users, `MongoUserRepository` in production and `MemoryUserRepository` in tests
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create(&self, user: &User) -> Result<(), Error>;
+
+    async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<User>, Error>;
+
+    async fn find_one_by_email(&self, email: String) -> Result<Option<User>, Error>;
+
+    /// Find one user whose email or username equals the given account
+    async fn find_one_by_account(&self, account: String) -> Result<Option<User>, Error>;
+
+    /// Find one page of the users matching the query,
+    /// returns the users, the total count of matching users and the cursor of the next page
+    async fn paginate(&self, query: &UserQuery) -> Result<(Vec<User>, u64, Option<String>), Error>;
+
+    /// Update the given fields atomically and return the updated user,
+    /// duplicate email or username is reported like the unique indexes do
+    async fn update_profile(
+        &self,
+        id: ObjectId,
+        username: Option<String>,
+        email: Option<String>,
+        locale: Option<Locale>,
+    ) -> Result<User, Error>;
+
+    /// Save the password, `updatedAt` and `sessionsRevokedAt` of the user
+    async fn update_password(&self, user: &User) -> Result<(), Error>;
+
+    async fn delete(&self, id: ObjectId) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MongoUserRepository {
+    db: Database,
+}
+
+impl MongoUserRepository {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl UserRepository for MongoUserRepository {
+    async fn create(&self, user: &User) -> Result<(), Error> {
+        let _timer = metrics::mongo_timer("users", "create");
+
+        self.db
+            .collection::<User>(Users)
+            .insert_one(user, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<User>, Error> {
+        let _timer = metrics::mongo_timer("users", "find_one_by_id");
+
+        let option = self
+            .db
+            .collection::<User>(Users)
+            .find_one(doc! { "_id": id }, None)
+            .await?;
+
+        Ok(option)
+    }
+
+    async fn find_one_by_email(&self, email: String) -> Result<Option<User>, Error> {
+        let _timer = metrics::mongo_timer("users", "find_one_by_email");
+
+        let option = self
+            .db
+            .collection::<User>(Users)
+            .find_one(doc! { "email": email }, None)
+            .await?;
+
+        Ok(option)
+    }
+
+    async fn find_one_by_account(&self, account: String) -> Result<Option<User>, Error> {
+        let _timer = metrics::mongo_timer("users", "find_one_by_account");
+
+        let option = self
+            .db
+            .collection::<User>(Users)
+            .find_one(
+                doc! {
+                    "$or": [
+                        { "email": account.to_owned() },
+                        { "username": account }
+                    ]
+                },
+                None,
+            )
+            .await?;
+
+        Ok(option)
+    }
+
+    async fn paginate(&self, query: &UserQuery) -> Result<(Vec<User>, u64, Option<String>), Error> {
+        let _timer = metrics::mongo_timer("users", "paginate");
+
+        let collection = self.db.collection::<User>(Users);
+        let filter = query.filter()?;
+        let direction = query.sort.direction();
+
+        let total = collection.count_documents(filter.to_owned(), None).await?;
+
+        let mut page_filter = filter;
+        let mut skip = None;
+
+        match query.cursor()? {
+            Some(cursor) => {
+                let operator = if direction > 0 { "$gt" } else { "$lt" };
+
+                page_filter.insert("_id", doc! { operator: cursor });
+            }
+            None => skip = Some(query.skip()),
+        }
+
+        // ObjectId grows with the creation time, fetch one more to know if there is a next page
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": direction })
+            .skip(skip)
+            .limit(query.limit + 1)
+            .build();
+
+        let mut users = collection
+            .find(page_filter, options)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let next_cursor = next_cursor(&mut users, query.limit);
+
+        Ok((users, total, next_cursor))
+    }
+
+    async fn update_profile(
+        &self,
+        id: ObjectId,
+        username: Option<String>,
+        email: Option<String>,
+        locale: Option<Locale>,
+    ) -> Result<User, Error> {
+        let _timer = metrics::mongo_timer("users", "update_profile");
+
+        let mut update = doc! { "updatedAt": DateTime::now() };
+
+        if let Some(username) = username {
+            update.insert("username", username);
+        }
+
+        if let Some(email) = email {
+            update.insert("email", email);
+        }
+
+        if let Some(locale) = locale {
+            update.insert("locale", locale);
+        }
+
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let option = self
+            .db
+            .collection::<User>(Users)
+            .find_one_and_update(doc! { "_id": id }, doc! { "$set": update }, options)
+            .await?;
+
+        match option {
+            Some(user) => Ok(user),
+            None => Err(NotFound(t_args(
+                "user.id_not_found",
+                &[("id", &id.to_hex())],
+            ))),
+        }
+    }
+
+    async fn update_password(&self, user: &User) -> Result<(), Error> {
+        let _timer = metrics::mongo_timer("users", "update_password");
+
+        let mut update = doc! {
+            "password": user.password.to_owned(),
+            "updatedAt": user.updated_at,
+        };
+
+        if let Some(sessions_revoked_at) = user.sessions_revoked_at {
+            update.insert("sessionsRevokedAt", sessions_revoked_at);
+        }
+
+        self.db
+            .collection::<User>(Users)
+            .update_one(doc! { "_id": user.id }, doc! { "$set": update }, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<(), Error> {
+        let _timer = metrics::mongo_timer("users", "delete");
+
+        self.db
+            .collection::<User>(Users)
+            .delete_one(doc! { "_id": id }, None)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps users in memory, enforcing the same unique email and username as the indexes
+#[derive(Debug, Default)]
+pub struct MemoryUserRepository {
+    users: Mutex<HashMap<ObjectId, User>>,
+}
+
+impl MemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<ObjectId, User>>, Error> {
+        self.users
+            .lock()
+            .map_err(|_| InternalServerError("User repository is poisoned".to_owned()))
+    }
+
+    /// Same message as a duplicate key error of the unique indexes
+    fn check_unique(
+        users: &HashMap<ObjectId, User>,
+        id: ObjectId,
+        username: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), Error> {
+        for user in users.values().filter(|user| user.id != id) {
+            if email == Some(user.email.as_str()) {
+                return Err(Duplicate(
+                    "email".to_owned(),
+                    t_args("user.email_exists", &[("email", &user.email)]),
+                ));
+            }
+
+            if username == Some(user.username.as_str()) {
+                return Err(Duplicate(
+                    "username".to_owned(),
+                    t_args("user.username_exists", &[("username", &user.username)]),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryUserRepository {
+    async fn create(&self, user: &User) -> Result<(), Error> {
+        let mut users = self.lock()?;
+
+        Self::check_unique(
+            &users,
+            user.id,
+            Some(user.username.as_str()),
+            Some(user.email.as_str()),
+        )?;
+
+        users.insert(user.id, user.to_owned());
+
+        Ok(())
+    }
+
+    async fn find_one_by_id(&self, id: ObjectId) -> Result<Option<User>, Error> {
+        Ok(self.lock()?.get(&id).cloned())
+    }
+
+    async fn find_one_by_email(&self, email: String) -> Result<Option<User>, Error> {
+        let option = self
+            .lock()?
+            .values()
+            .find(|user| user.email == email)
+            .cloned();
+
+        Ok(option)
+    }
+
+    async fn find_one_by_account(&self, account: String) -> Result<Option<User>, Error> {
+        let option = self
+            .lock()?
+            .values()
+            .find(|user| user.email == account || user.username == account)
+            .cloned();
+
+        Ok(option)
+    }
+
+    async fn paginate(&self, query: &UserQuery) -> Result<(Vec<User>, u64, Option<String>), Error> {
+        let (from, to) = query.created_range()?;
+        let cursor = query.cursor()?;
+        let search = query
+            .search
+            .as_ref()
+            .filter(|search| !search.is_empty())
+            .map(|search| search.to_lowercase());
+        let ascending = query.sort.direction() > 0;
+
+        let mut users = self
+            .lock()?
+            .values()
+            .filter(|user| {
+                query.role.as_ref().is_none_or(|role| user.role == *role)
+                    && search.as_ref().is_none_or(|search| {
+                        user.email.to_lowercase().contains(search)
+                            || user.username.to_lowercase().contains(search)
+                    })
+                    && from.is_none_or(|from| user.created_at >= from)
+                    && to.is_none_or(|to| user.created_at < to)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        users.sort_by_key(|user| user.id);
+
+        if !ascending {
+            users.reverse();
+        }
+
+        let total = users.len() as u64;
+
+        let start = match cursor {
+            Some(cursor) => users
+                .iter()
+                .position(|user| {
+                    if ascending {
+                        user.id > cursor
+                    } else {
+                        user.id < cursor
+                    }
+                })
+                .unwrap_or(users.len()),
+            None => query.skip() as usize,
+        };
+
+        let mut users = users
+            .into_iter()
+            .skip(start)
+            .take(query.limit as usize + 1)
+            .collect::<Vec<_>>();
+
+        let next_cursor = next_cursor(&mut users, query.limit);
+
+        Ok((users, total, next_cursor))
+    }
+
+    async fn update_profile(
+        &self,
+        id: ObjectId,
+        username: Option<String>,
+        email: Option<String>,
+        locale: Option<Locale>,
+    ) -> Result<User, Error> {
+        let mut users = self.lock()?;
+
+        Self::check_unique(&users, id, username.as_deref(), email.as_deref())?;
+
+        let user = match users.get_mut(&id) {
+            Some(user) => user,
+            None => {
+                return Err(NotFound(t_args(
+                    "user.id_not_found",
+                    &[("id", &id.to_hex())],
+                )))
+            }
+        };
+
+        if let Some(username) = username {
+            user.username = username;
+        }
+
+        if let Some(email) = email {
+            user.email = email;
+        }
+
+        if let Some(locale) = locale {
+            user.locale = Some(locale);
+        }
+
+        user.updated_at = DateTime::now();
+
+        Ok(user.to_owned())
+    }
+
+    async fn update_password(&self, user: &User) -> Result<(), Error> {
+        if let Some(stored) = self.lock()?.get_mut(&user.id) {
+            stored.password = user.password.to_owned();
+            stored.updated_at = user.updated_at;
+            stored.sessions_revoked_at = user.sessions_revoked_at;
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: ObjectId) -> Result<(), Error> {
+        self.lock()?.remove(&id);
+
+        Ok(())
+    }
+}
+
+/// The page is fetched with one more user than the limit, which tells if there is a next page
+fn next_cursor(users: &mut Vec<User>, limit: i64) -> Option<String> {
+    if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|user| user.id.to_hex())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+    use serde_json::{json, Value};
+
+    use super::{MemoryUserRepository, UserRepository};
+    use crate::models::users::{query::UserQuery, role::Role, User};
+
+    /// Repository with users `user0` to `user{count - 1}`, created in this order
+    async fn repository(count: usize) -> (MemoryUserRepository, Vec<User>) {
+        let repository = MemoryUserRepository::new();
+        let template = User::new(
+            String::new(),
+            String::new(),
+            "Passw0rd".to_owned(),
+            Role::User,
+        );
+
+        let mut users = Vec::new();
+
+        for i in 0..count {
+            let user = User {
+                id: ObjectId::new(),
+                email: format!("user{}@example.com", i),
+                username: format!("user{}", i),
+                role: if i == 0 { Role::Admin } else { Role::User },
+                ..template.to_owned()
+            };
+
+            repository.create(&user).await.unwrap();
+            users.push(user);
+        }
+
+        (repository, users)
+    }
+
+    fn query(value: Value) -> UserQuery {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn usernames(users: &[User]) -> Vec<&str> {
+        users.iter().map(|user| user.username.as_str()).collect()
+    }
+
+    #[actix_web::test]
+    async fn paginate_newest_first_by_page() {
+        let (repository, _) = repository(5).await;
+
+        let (users, total, next_cursor) = repository
+            .paginate(&query(json!({ "page": 2, "limit": 2 })))
+            .await
+            .unwrap();
+
+        assert_eq!(usernames(&users), vec!["user2", "user1"]);
+        assert_eq!(total, 5);
+        assert!(next_cursor.is_some());
+    }
+
+    #[actix_web::test]
+    async fn paginate_by_cursor_until_the_last_page() {
+        let (repository, _) = repository(5).await;
+
+        let mut query = query(json!({ "limit": 2, "sort": "asc" }));
+        let mut pages = Vec::new();
+
+        loop {
+            let (users, _, next_cursor) = repository.paginate(&query).await.unwrap();
+            pages.push(usernames(&users).join(","));
+
+            match next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, vec!["user0,user1", "user2,user3", "user4"]);
+    }
+
+    #[actix_web::test]
+    async fn paginate_with_filters() {
+        let (repository, _) = repository(12).await;
+
+        let (users, total, _) = repository
+            .paginate(&query(json!({ "search": "USER1" })))
+            .await
+            .unwrap();
+        assert_eq!(usernames(&users), vec!["user11", "user10", "user1"]);
+        assert_eq!(total, 3);
+
+        let (users, total, next_cursor) = repository
+            .paginate(&query(json!({ "role": "admin" })))
+            .await
+            .unwrap();
+        assert_eq!(usernames(&users), vec!["user0"]);
+        assert_eq!(total, 1);
+        assert!(next_cursor.is_none());
+    }
+}
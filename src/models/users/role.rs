@@ -1,17 +1,53 @@
+use mongodb::bson::Bson;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
 
-#[derive(Debug, Serialize, Clone)]
+use crate::i18n::t_args;
+
+/// Roles are ordered by privilege: Root > Admin > Author > User
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum Role {
     Root,
     Admin,
     Author,
+    #[default]
     User,
 }
 
-impl Default for Role {
-    fn default() -> Self {
-        Self::User
+impl Role {
+    fn level(&self) -> u8 {
+        match self {
+            Self::Root => 3,
+            Self::Admin => 2,
+            Self::Author => 1,
+            Self::User => 0,
+        }
+    }
+}
+
+impl PartialOrd for Role {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Role {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.level().cmp(&other.level())
+    }
+}
+
+impl From<Role> for Bson {
+    fn from(role: Role) -> Self {
+        use Role::*;
+
+        match role {
+            Root => Bson::String("root".to_owned()),
+            Admin => Bson::String("admin".to_owned()),
+            Author => Bson::String("author".to_owned()),
+            User => Bson::String("user".to_owned()),
+        }
     }
 }
 
@@ -27,10 +63,37 @@ impl<'de> Deserialize<'de> for Role {
             "admin" => Ok(Self::Admin),
             "author" => Ok(Self::Author),
             "user" => Ok(Self::User),
-            _ => Err(serde::de::Error::custom(format!(
-                "未知的角色: {}，应为 root, admin, author, user",
-                s
+            _ => Err(serde::de::Error::custom(t_args(
+                "user.invalid_role",
+                &[("role", &s)],
             ))),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Role::{self, *};
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(Root > Admin);
+        assert!(Admin > Author);
+        assert!(Author > User);
+
+        let mut roles = vec![Author, Root, User, Admin];
+        roles.sort();
+        assert_eq!(roles, vec![User, Author, Admin, Root]);
+    }
+
+    #[test]
+    fn default_role_is_user() {
+        assert_eq!(Role::default(), User);
+    }
+
+    #[test]
+    fn deserialize_known_roles_only() {
+        assert_eq!(serde_json::from_str::<Role>("\"admin\"").unwrap(), Admin);
+        assert!(serde_json::from_str::<Role>("\"owner\"").is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{i18n::Locale, utils::regex::REGEX_USERNAME};
+
+/// Fields of a user which can be changed directly,
+/// the email is changed by its owner through `/users/me/email` with a code,
+/// so other fields like `email` are rejected instead of silently ignored
+#[derive(Debug, Deserialize, Default, Validate)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct UserUpdater {
+    #[validate(regex(path = "REGEX_USERNAME", code = "invalid_username"))]
+    pub username: Option<String>,
+    /// Language of the emails and messages
+    pub locale: Option<Locale>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::UserUpdater;
+
+    #[test]
+    fn reject_the_email() {
+        let updater = json!({ "username": "alice", "email": "alice@example.com" });
+
+        assert!(serde_json::from_value::<UserUpdater>(updater).is_err());
+    }
+
+    #[test]
+    fn accept_partial_updates() {
+        let updater = serde_json::from_value::<UserUpdater>(json!({ "locale": "zh" })).unwrap();
+
+        assert!(updater.username.is_none());
+        assert!(updater.locale.is_some());
+    }
+}
@@ -1,5 +1,15 @@
-use actix_web::HttpResponse;
+use actix_web::{http::StatusCode, HttpResponse};
+
+use crate::{
+    errors::{ErrorBody, ErrorCode},
+    i18n::t,
+};
 
 pub async fn not_found() -> HttpResponse {
-    HttpResponse::NotFound().body("Not Found")
+    ErrorBody::new(
+        StatusCode::NOT_FOUND,
+        ErrorCode::NotFound,
+        t("error.not_found"),
+    )
+    .respond()
 }
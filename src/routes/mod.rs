@@ -1,15 +1,29 @@
-use actix_web::web::{route, scope, JsonConfig, ServiceConfig};
+use actix_web::web::{get, route, scope, JsonConfig, ServiceConfig};
 use serde_qs::actix::QsQueryConfig;
 
-use crate::errors::json::json_error_handler;
+use crate::{
+    controllers::{
+        health::{liveness, readiness},
+        metrics::scrape,
+    },
+    errors::{json::json_error_handler, query::query_error_handler},
+};
 
 mod default;
 mod users;
 
 pub fn configure(config: &mut ServiceConfig) {
     config
+        .route("/healthz", get().to(liveness))
+        .route("/readyz", get().to(readiness))
         .service(scope("/api").service(scope("/v1").service(users::router())))
         .default_service(route().to(default::not_found))
         .app_data(JsonConfig::default().error_handler(json_error_handler))
-        .app_data(QsQueryConfig::default());
+        .app_data(QsQueryConfig::default().error_handler(query_error_handler));
+}
+
+/// `/metrics`, alone on the admin address of `metrics.listen`, on the main listeners
+/// without it or with `metrics.public`
+pub fn metrics(config: &mut ServiceConfig) {
+    config.route("/metrics", get().to(scrape));
 }
@@ -1,13 +1,80 @@
 use actix_web::{
-    web::{post, resource, scope},
+    web::{delete, get, patch, post, resource, scope},
     Scope,
 };
 
-use crate::controllers::users::{auth::register, codes::send_registration_code};
+use crate::{
+    controllers::users::{
+        accounts::{delete_user, get_user, list_users, update_user},
+        auth::{login, logout, register, reset_password},
+        codes::{send_email_change_code, send_password_reset_code, send_registration_code},
+        profile::{change_email, change_password, me},
+    },
+    middlewares::{
+        rate_limit::{RateLimit, RateLimitPolicy},
+        role::RequireRole,
+    },
+    models::users::role::Role,
+};
 
 pub fn router() -> Scope {
     scope("users")
-        .service(resource("registration-code").route(post().to(send_registration_code)))
-        .service(resource("register").route(post().to(register)))
-        .service(scope("{id}"))
+        .service(
+            resource("")
+                .wrap(RequireRole::new(Role::Admin))
+                .route(get().to(list_users)),
+        )
+        .service(
+            resource("registration-code")
+                .wrap(RateLimit::new(RateLimitPolicy::Code))
+                .route(post().to(send_registration_code)),
+        )
+        .service(
+            resource("register")
+                .wrap(RateLimit::new(RateLimitPolicy::Auth))
+                .route(post().to(register)),
+        )
+        .service(
+            resource("password-reset-code")
+                .wrap(RateLimit::new(RateLimitPolicy::Code))
+                .route(post().to(send_password_reset_code)),
+        )
+        .service(
+            resource("password-reset")
+                .wrap(RateLimit::new(RateLimitPolicy::Auth))
+                .route(post().to(reset_password)),
+        )
+        .service(
+            resource("login")
+                .wrap(RateLimit::new(RateLimitPolicy::Auth))
+                .route(post().to(login)),
+        )
+        .service(resource("logout").route(post().to(logout)))
+        .service(
+            scope("me")
+                .service(resource("").route(get().to(me)))
+                .service(
+                    resource("password")
+                        .wrap(RateLimit::new(RateLimitPolicy::Auth))
+                        .route(post().to(change_password)),
+                )
+                .service(
+                    resource("email-code")
+                        .wrap(RateLimit::new(RateLimitPolicy::Code))
+                        .route(post().to(send_email_change_code)),
+                )
+                .service(
+                    resource("email")
+                        .wrap(RateLimit::new(RateLimitPolicy::Auth))
+                        .route(post().to(change_email)),
+                ),
+        )
+        .service(
+            scope("{id}").service(
+                resource("")
+                    .route(get().to(get_user))
+                    .route(patch().to(update_user))
+                    .route(delete().to(delete_user)),
+            ),
+        )
 }
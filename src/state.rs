@@ -1,32 +1,61 @@
+use std::sync::Arc;
+
 use crate::{
     config::Config,
-    database::{redis::Redis, Database},
-    utils::email::Email,
+    database::Database,
+    models::{
+        outbox::repository::{MongoOutboxRepository, OutboxRepository},
+        users::{
+            codes::repository::{CodeRepository, MongoCodeRepository},
+            repository::{MongoUserRepository, UserRepository},
+        },
+    },
+    utils::{email::Email, shutdown::Shutdown},
 };
 
 #[derive(Clone)]
 pub struct State {
     pub config: Config,
-    pub database: Database,
-    pub redis: Redis,
+    /// Connection behind the mongodb repositories, `None` with other repositories
+    pub database: Option<Database>,
     pub email: Email,
+    pub users: Arc<dyn UserRepository>,
+    pub codes: Arc<dyn CodeRepository>,
+    pub outbox: Arc<dyn OutboxRepository>,
+    pub shutdown: Shutdown,
 }
 
 impl State {
-    pub async fn new() -> Self {
-        let config = Config::default();
-        let mongo_config = config.mongo_config.to_owned();
-        let email_config = config.email_config.to_owned();
+    /// State with the repositories backed by mongodb
+    pub fn new(config: Config, database: &Database) -> Self {
+        let email = Email::new(config.email_config.to_owned());
+        let users = Arc::new(MongoUserRepository::new(database.to_owned()));
+        let codes = Arc::new(MongoCodeRepository::new(database.to_owned()));
+        let outbox = Arc::new(MongoOutboxRepository::new(database.to_owned()));
 
-        let database = Database::new(mongo_config).await;
-        let redis = Redis::new(config.redis_url.to_owned()).await;
-        let email = Email::new(email_config);
+        Self {
+            database: Some(database.to_owned()),
+            ..Self::with_repositories(config, email, users, codes, outbox)
+        }
+    }
 
+    /// State with the given repositories and no mongodb connection,
+    /// like the in-memory repositories of the tests
+    pub fn with_repositories(
+        config: Config,
+        email: Email,
+        users: Arc<dyn UserRepository>,
+        codes: Arc<dyn CodeRepository>,
+        outbox: Arc<dyn OutboxRepository>,
+    ) -> Self {
         Self {
             config,
-            database,
-            redis,
+            database: None,
             email,
+            users,
+            codes,
+            outbox,
+            shutdown: Shutdown::default(),
         }
     }
 }
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use crate::{
+    config::{Config, Profile, Source},
+    models::{
+        outbox::repository::MemoryOutboxRepository,
+        users::{codes::repository::MemoryCodeRepository, repository::MemoryUserRepository},
+    },
+    state::State,
+    utils::email::{mailers::StubMailer, Email, Templates},
+};
+
+/// Layers of a valid test configuration, more can be set on top of it
+pub fn source() -> Source {
+    Source::new(Profile::Test)
+        .set("redis.url", "redis://localhost")
+        .set("code.secret", "secret")
+        .set("mongo.url", "mongodb://localhost")
+        .set("mongo.db_name", "test")
+        .set("email.from", "noreply@example.com")
+        .set("email.reply_to", "support@example.com")
+        .set("email.transport", "stub")
+}
+
+pub fn config() -> Config {
+    Config::from_source(&source()).expect("the test config is valid")
+}
+
+/// State backed by the in-memory repositories and the stub mailer,
+/// which are kept so the tests can look into them
+pub struct Fixture {
+    pub state: State,
+    pub mailer: Arc<StubMailer>,
+    pub outbox: Arc<MemoryOutboxRepository>,
+    pub users: Arc<MemoryUserRepository>,
+    pub codes: Arc<MemoryCodeRepository>,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::with_config(config())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        let mailer = Arc::new(StubMailer::new());
+        let templates = Templates::embedded().expect("the embedded templates are valid");
+        let email = Email::with_mailer(
+            config.email_config.from.to_owned(),
+            config.email_config.reply_to.to_owned(),
+            mailer.clone(),
+            Arc::new(templates),
+        );
+
+        let users = Arc::new(MemoryUserRepository::new());
+        let outbox = Arc::new(MemoryOutboxRepository::new());
+        let codes = Arc::new(MemoryCodeRepository::new());
+
+        let state =
+            State::with_repositories(config, email, users.clone(), codes.clone(), outbox.clone());
+
+        Self {
+            state,
+            mailer,
+            outbox,
+            users,
+            codes,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use lettre::{
+    transport::{smtp::authentication::Credentials, stub::AsyncStubTransport},
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::{path::PathBuf, process, sync::Arc};
+
+use crate::{config::EmailTransport, errors::Error};
+
+/// Delivers the emails built by [`super::Email`]
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: Message) -> Result<(), Error>;
+
+    /// Check the backend can deliver, only the SMTP server can be unreachable
+    async fn test_connection(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Build the mailer selected by the configuration
+pub fn from_config(transport: EmailTransport) -> Arc<dyn Mailer> {
+    match transport {
+        EmailTransport::Smtp {
+            host,
+            port,
+            username,
+            password,
+        } => Arc::new(SmtpMailer::new(&host, port, username, password)),
+        EmailTransport::File(dir) => Arc::new(FileMailer::new(dir)),
+        EmailTransport::Stub => Arc::new(StubMailer::new()),
+        EmailTransport::Log => Arc::new(LogMailer),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, port: u16, username: String, password: String) -> Self {
+        let credentials = Credentials::new(username, password);
+        let transport = match AsyncSmtpTransport::<Tokio1Executor>::relay(host) {
+            Ok(builder) => builder.credentials(credentials).port(port).build(),
+            Err(e) => {
+                tracing::error!("Failed to create transport: {:?}", e);
+                process::exit(1);
+            }
+        };
+
+        Self { transport }
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+
+    async fn test_connection(&self) -> Result<(), Error> {
+        if self.transport.test_connection().await? {
+            Ok(())
+        } else {
+            Err(Error::InternalServerError(
+                "SMTP server refused the connection".to_owned(),
+            ))
+        }
+    }
+}
+
+/// Write each email as an `.eml` file, which can be opened by any mail client
+pub struct FileMailer {
+    dir: PathBuf,
+    transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> Self {
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            tracing::error!("Failed to create the email directory {:?}: {:?}", dir, e);
+            process::exit(1);
+        }
+
+        let transport = AsyncFileTransport::new(&dir);
+
+        Self { dir, transport }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        let id = self.transport.send(message).await?;
+
+        tracing::info!("Email written to {:?}", self.dir.join(format!("{id}.eml")));
+
+        Ok(())
+    }
+}
+
+/// Keep the emails in memory, so that tests can read them back
+#[derive(Clone)]
+pub struct StubMailer {
+    transport: AsyncStubTransport,
+}
+
+impl Default for StubMailer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StubMailer {
+    pub fn new() -> Self {
+        Self {
+            transport: AsyncStubTransport::new_ok(),
+        }
+    }
+
+    /// The formatted emails sent so far, with their recipients
+    pub async fn messages(&self) -> Vec<(Vec<String>, String)> {
+        self.transport
+            .messages()
+            .await
+            .into_iter()
+            .map(|(envelope, message)| {
+                let to = envelope.to().iter().map(ToString::to_string).collect();
+
+                (to, message)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Mailer for StubMailer {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        if self.transport.send(message).await.is_err() {
+            return Err(Error::InternalServerError(
+                "Failed to send the email".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Print the emails to the log instead of sending them
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        let to = message
+            .envelope()
+            .to()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        tracing::info!(
+            "Email to {}:\n{}",
+            to,
+            String::from_utf8_lossy(&message.formatted())
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use lettre::{message::MultiPart, Message};
+use serde_json::{json, Value};
+use std::{process, sync::Arc};
+
+use crate::{
+    config::EmailConfig,
+    errors::Error,
+    i18n::{translate, Locale},
+    metrics,
+};
+
+pub mod mailers;
+pub mod templates;
+mod text;
+
+pub use mailers::Mailer;
+pub use templates::{EmailTemplate, Templates};
+
+#[derive(Clone)]
+pub struct Email {
+    from: String,
+    reply_to: String,
+    mailer: Arc<dyn Mailer>,
+    templates: Arc<Templates>,
+}
+
+impl Email {
+    pub fn new(email_config: EmailConfig) -> Self {
+        let templates = match &email_config.template_dir {
+            Some(dir) => Templates::from_dir(dir, email_config.template_reload),
+            None => Templates::embedded(),
+        };
+
+        let templates = match templates {
+            Ok(templates) => templates,
+            Err(e) => {
+                tracing::error!("Failed to load email templates: {:?}", e);
+                process::exit(1);
+            }
+        };
+
+        let mailer = mailers::from_config(email_config.transport);
+
+        Self::with_mailer(
+            email_config.from,
+            email_config.reply_to,
+            mailer,
+            Arc::new(templates),
+        )
+    }
+
+    /// Use a given mailer, e.g. a shared [`mailers::StubMailer`] whose messages are inspected by tests
+    pub fn with_mailer(
+        from: String,
+        reply_to: String,
+        mailer: Arc<dyn Mailer>,
+        templates: Arc<Templates>,
+    ) -> Self {
+        Self {
+            from,
+            reply_to,
+            mailer,
+            templates,
+        }
+    }
+
+    /// Check the mailer can deliver, see [`Mailer::test_connection`]
+    pub async fn test_connection(&self) -> Result<(), Error> {
+        self.mailer.test_connection().await
+    }
+
+    //"/Users/headiron/codes/headiron-rust/target/release"
+    //"/Users/headiron/codes/headiron-rust/target/release/headiron-rust"
+
+    //"/Users/headiron/codes/headiron-rust"
+    //"/Users/headiron/codes/headiron-rust/target/debug/headiron-rust"
+
+    /// Send the code in the language of the locale, the subject and header come from the catalogs
+    pub async fn send_code(
+        &self,
+        to: String,
+        locale: Locale,
+        template: EmailTemplate,
+        code: String,
+        code_expire: i64,
+    ) -> Result<(), Error> {
+        let subject = translate(locale, &format!("email.{}.subject", template.name()), &[]);
+        let header = translate(locale, &format!("email.{}.header", template.name()), &[]);
+
+        let data = json!({
+            "locale": locale,
+            "title": subject,
+            "header": header,
+            "code": code,
+            "code_expire": code_expire,
+        });
+
+        let email = self.generate_email(to, template, &subject, data)?;
+
+        self.send(template, email).await
+    }
+
+    /// Send an email without code, informing the user about a change of the account
+    pub async fn send_notification(
+        &self,
+        to: String,
+        locale: Locale,
+        subject: &str,
+        header: &str,
+        message: String,
+    ) -> Result<(), Error> {
+        let data = json!({
+            "locale": locale,
+            "title": subject,
+            "header": header,
+            "message": message,
+        });
+
+        let email = self.generate_email(to, EmailTemplate::SecurityAlert, subject, data)?;
+
+        self.send(EmailTemplate::SecurityAlert, email).await
+    }
+
+    /// Hand the message to the mailer, counted by template and result
+    async fn send(&self, template: EmailTemplate, email: Message) -> Result<(), Error> {
+        let result = self.mailer.send(email).await;
+
+        metrics::count_email(template.name(), result.is_ok());
+
+        result
+    }
+
+    fn generate_email(
+        &self,
+        to: String,
+        template: EmailTemplate,
+        subject: &str,
+        data: Value,
+    ) -> Result<Message, Error> {
+        let (html, text) = self.templates.render(template, &data)?;
+
+        let message = Message::builder()
+            .from(self.from.parse()?)
+            .reply_to(self.reply_to.parse()?)
+            .to(to.parse()?)
+            .subject(subject)
+            .date_now()
+            .multipart(MultiPart::alternative_plain_html(text, html))?;
+
+        Ok(message)
+    }
+}
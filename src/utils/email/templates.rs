@@ -0,0 +1,172 @@
+use handlebars::{
+    html_escape, Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::{
+    errors::Error,
+    i18n::{translate, Locale},
+    models::users::codes::CodeType,
+    utils::email::text::html_to_text,
+};
+
+/// Layout and partials shared by the emails, rendered through `{{> name}}`
+const PARTIALS: [(&str, &str); 2] = [
+    ("layout", include_str!("../../assets/emails/layout.html")),
+    ("code", include_str!("../../assets/emails/code.html")),
+];
+
+/// Emails sent by the application, each one is a template named after `EmailTemplate::name`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTemplate {
+    Registration,
+    PasswordReset,
+    EmailChange,
+    /// A change of the account the user should know about
+    SecurityAlert,
+}
+
+impl EmailTemplate {
+    const ALL: [EmailTemplate; 4] = [
+        EmailTemplate::Registration,
+        EmailTemplate::PasswordReset,
+        EmailTemplate::EmailChange,
+        EmailTemplate::SecurityAlert,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        use EmailTemplate::*;
+
+        match self {
+            Registration => "registration",
+            PasswordReset => "password_reset",
+            EmailChange => "email_change",
+            SecurityAlert => "security_alert",
+        }
+    }
+
+    fn source(&self) -> &'static str {
+        use EmailTemplate::*;
+
+        match self {
+            Registration => include_str!("../../assets/emails/registration.html"),
+            PasswordReset => include_str!("../../assets/emails/password_reset.html"),
+            EmailChange => include_str!("../../assets/emails/email_change.html"),
+            SecurityAlert => include_str!("../../assets/emails/security_alert.html"),
+        }
+    }
+}
+
+impl From<CodeType> for EmailTemplate {
+    fn from(code_type: CodeType) -> Self {
+        match code_type {
+            CodeType::Registration => EmailTemplate::Registration,
+            CodeType::PasswordReset => EmailTemplate::PasswordReset,
+            CodeType::EmailChange => EmailTemplate::EmailChange,
+        }
+    }
+}
+
+/// Registry of the email templates, built once at startup
+pub struct Templates {
+    handlebars: Handlebars<'static>,
+}
+
+impl Templates {
+    /// Templates embedded in the binary
+    pub fn embedded() -> Result<Self, Error> {
+        let mut handlebars = Handlebars::new();
+
+        handlebars.register_helper("t", Box::new(t_helper));
+
+        for (name, source) in PARTIALS {
+            handlebars
+                .register_template_string(name, source)
+                .map_err(Box::new)?;
+        }
+
+        for template in EmailTemplate::ALL {
+            handlebars
+                .register_template_string(template.name(), template.source())
+                .map_err(Box::new)?;
+        }
+
+        Ok(Self { handlebars })
+    }
+
+    /// Templates read from `{dir}/{name}.html`, with the same names as the embedded ones,
+    /// with `reload` the files are read again on each render, for editing them in development
+    pub fn from_dir(dir: &Path, reload: bool) -> Result<Self, Error> {
+        let mut handlebars = Handlebars::new();
+
+        // must be set before the templates are registered
+        handlebars.set_dev_mode(reload);
+        handlebars.register_helper("t", Box::new(t_helper));
+
+        let names = PARTIALS
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(EmailTemplate::ALL.iter().map(EmailTemplate::name));
+
+        for name in names {
+            handlebars
+                .register_template_file(name, dir.join(format!("{name}.html")))
+                .map_err(Box::new)?;
+        }
+
+        Ok(Self { handlebars })
+    }
+
+    /// Render the html of the email and its text/plain alternative
+    pub fn render(&self, template: EmailTemplate, data: &Value) -> Result<(String, String), Error> {
+        let html = self.handlebars.render(template.name(), data)?;
+        let text = html_to_text(&html);
+
+        Ok((html, text))
+    }
+}
+
+/// `{{t "key" name=value}}`, the message of the key in the `locale` of the data,
+/// with the `{name}` placeholders replaced by the hash arguments
+fn t_helper(
+    helper: &Helper,
+    _: &Handlebars,
+    context: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let key = helper
+        .param(0)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| RenderError::new("Helper `t` requires the key of a message"))?;
+
+    let locale = context
+        .data()
+        .get("locale")
+        .and_then(Value::as_str)
+        .and_then(Locale::from_tag)
+        .unwrap_or_default();
+
+    let args = helper
+        .hash()
+        .iter()
+        .map(|(name, value)| {
+            let value = match value.value() {
+                Value::String(value) => value.to_owned(),
+                value => value.to_string(),
+            };
+
+            (*name, value)
+        })
+        .collect::<Vec<_>>();
+
+    let args = args
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect::<Vec<_>>();
+
+    out.write(&html_escape(&translate(locale, key, &args)))?;
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // head, style and script elements have no visible text
+    static ref REGEX_INVISIBLE: Regex =
+        Regex::new(r"(?is)<(head|style|script)\b.*?</(head|style|script)>").unwrap();
+    // including the conditional comments of outlook
+    static ref REGEX_COMMENT: Regex = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    static ref REGEX_LINE_BREAK: Regex =
+        Regex::new(r"(?i)<br\s*/?>|</(p|div|tr|h[1-6]|li|table)>").unwrap();
+    static ref REGEX_TAG: Regex = Regex::new(r"(?s)<[^>]*>").unwrap();
+}
+
+/// Text/plain alternative of a rendered html email
+pub fn html_to_text(html: &str) -> String {
+    let text = REGEX_INVISIBLE.replace_all(html, "");
+    let text = REGEX_COMMENT.replace_all(&text, "");
+    let text = REGEX_LINE_BREAK.replace_all(&text, "\n");
+    let text = REGEX_TAG.replace_all(&text, "");
+
+    let lines = text
+        .lines()
+        .map(|line| decode_entities(&line.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+
+    lines.join("\n\n")
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&#160;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&#x3D;", "=")
+        .replace("&#x60;", "`")
+        .replace("&amp;", "&")
+        .trim()
+        .to_owned()
+}
@@ -0,0 +1,45 @@
+use std::env::var;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// Format of the log lines, from `LOG_FORMAT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human readable lines, the spans of a line are printed before its message
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the current span,
+    /// like the `request_id` of the request
+    Json,
+}
+
+impl LogFormat {
+    fn from_env() -> Self {
+        match var("LOG_FORMAT") {
+            Ok(format) => match format.to_lowercase().as_str() {
+                "json" => LogFormat::Json,
+                "text" => LogFormat::Text,
+                _ => {
+                    eprintln!("LOG_FORMAT `{}` is unknown, using `text`", format);
+                    LogFormat::Text
+                }
+            },
+            Err(_) => LogFormat::Text,
+        }
+    }
+}
+
+/// Install the global subscriber, filtered by `RUST_LOG` with `info` by default,
+/// the records of the `log` macros of the dependencies go through it as well, inside the current span
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = fmt().with_env_filter(filter);
+
+    match LogFormat::from_env() {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
@@ -1,3 +1,8 @@
 pub mod email;
+pub mod logging;
 pub mod regex;
+pub mod session;
+pub mod shutdown;
+pub mod systemd;
+pub mod tls;
 pub mod validation;
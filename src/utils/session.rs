@@ -0,0 +1,49 @@
+use actix_identity::Identity;
+use actix_session::{Session, SessionExt};
+use actix_web::{HttpMessage, HttpRequest};
+use mongodb::bson::DateTime;
+
+use crate::{errors::Error, i18n::Locale, models::users::User};
+
+const LOGGED_IN_AT: &str = "loggedInAt";
+const LOCALE: &str = "locale";
+
+/// Attach the user to the session of the request,
+/// the login time is kept so the session can be ended by revoking the user's sessions
+pub fn login(request: &HttpRequest, user: &User) -> Result<(), Error> {
+    Identity::login(&request.extensions(), user.id.to_hex())?;
+
+    let session = request.get_session();
+
+    if let Some(locale) = user.preferred_locale() {
+        set_locale(&session, locale)?;
+    }
+
+    stamp(&session)
+}
+
+/// Mark the session as logged in now, unit is millisecond
+pub fn stamp(session: &Session) -> Result<(), Error> {
+    session.insert(LOGGED_IN_AT, DateTime::now().timestamp_millis())?;
+
+    Ok(())
+}
+
+pub fn logged_in_at(session: &Session) -> Result<Option<i64>, Error> {
+    let option = session.get::<i64>(LOGGED_IN_AT)?;
+
+    Ok(option)
+}
+
+/// Preferred locale of the logged in user, used by `middlewares::locale::Localization`
+pub fn locale(session: &Session) -> Result<Option<Locale>, Error> {
+    let option = session.get::<Locale>(LOCALE)?;
+
+    Ok(option)
+}
+
+pub fn set_locale(session: &Session, locale: Locale) -> Result<(), Error> {
+    session.insert(LOCALE, locale)?;
+
+    Ok(())
+}
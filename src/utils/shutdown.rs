@@ -0,0 +1,55 @@
+use actix_web::{dev::ServerHandle, rt::time::sleep};
+use futures::future::{join_all, select, Either};
+use std::{
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+/// Set once the server is asked to stop, `/readyz` then reports not ready
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    pub fn is_started(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn start(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// On SIGTERM or SIGINT, report not ready for `delay` seconds so the orchestrator
+/// stops routing traffic, then stop the servers gracefully,
+/// replaces the signal handling of `HttpServer`, which must be disabled
+pub async fn on_signal(handles: Vec<ServerHandle>, shutdown: Shutdown, delay: u64) {
+    let (mut terminate, mut interrupt) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+        (Err(e), _) | (_, Err(e)) => {
+            return error!("Failed to listen to the shutdown signals: {}", e);
+        }
+    };
+
+    let signal = match select(pin!(terminate.recv()), pin!(interrupt.recv())).await {
+        Either::Left(_) => "SIGTERM",
+        Either::Right(_) => "SIGINT",
+    };
+
+    info!(
+        "Received {}, not ready anymore, stopping in {} seconds",
+        signal, delay
+    );
+
+    shutdown.start();
+    sleep(Duration::from_secs(delay)).await;
+    join_all(handles.iter().map(|handle| handle.stop(true))).await;
+}
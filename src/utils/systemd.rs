@@ -0,0 +1,68 @@
+use std::{
+    env::var,
+    io::{Error, ErrorKind, Result},
+    net::TcpListener,
+    os::unix::{
+        io::{FromRawFd, IntoRawFd, RawFd},
+        net::UnixListener,
+    },
+    process,
+};
+
+/// First file descriptor passed by systemd, `SD_LISTEN_FDS_START`
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed by systemd, already bound and listening
+pub enum InheritedListener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Sockets passed by systemd socket activation, through `LISTEN_FDS` and `LISTEN_PID`
+pub fn listeners() -> Result<Vec<InheritedListener>> {
+    let fds = var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<RawFd>().ok())
+        .filter(|fds| *fds > 0)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                "LISTEN_FDS is not set, the server was not started by systemd socket activation",
+            )
+        })?;
+
+    if let Ok(pid) = var("LISTEN_PID") {
+        if pid.parse::<u32>().ok() != Some(process::id()) {
+            return Err(Error::other(
+                "LISTEN_PID is another process, the sockets were not passed to this one",
+            ));
+        }
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + fds)
+        .map(listener)
+        .collect()
+}
+
+fn listener(fd: RawFd) -> Result<InheritedListener> {
+    // SAFETY: systemd passes the descriptors from `LISTEN_FDS_START` to this process,
+    // they are taken once and nothing else in the process uses them
+    let tcp = unsafe { TcpListener::from_raw_fd(fd) };
+
+    // the address of a unix socket is not an `Inet` address
+    if tcp.local_addr().is_ok() {
+        return Ok(InheritedListener::Tcp(tcp));
+    }
+
+    let fd = tcp.into_raw_fd();
+    // SAFETY: same descriptor, released by the `TcpListener` above
+    let unix = unsafe { UnixListener::from_raw_fd(fd) };
+
+    match unix.local_addr() {
+        Ok(_) => Ok(InheritedListener::Unix(unix)),
+        Err(e) => Err(Error::new(
+            e.kind(),
+            format!("File descriptor {} is not a TCP or unix socket: {}", fd, e),
+        )),
+    }
+}
@@ -0,0 +1,151 @@
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{any_supported_type, CertifiedKey},
+    Certificate, PrivateKey, ServerConfig,
+};
+use rustls_pemfile::Item;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+use crate::config::TlsConfig;
+
+/// Certificate chain and private key of the PEM files, ready for rustls
+pub fn load(cert: &Path, key: &Path) -> Result<CertifiedKey, String> {
+    let certs = read_pem(cert)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(cert) => Some(Certificate(cert)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if certs.is_empty() {
+        return Err(format!("No certificate in {}", cert.display()));
+    }
+
+    let private_key = read_pem(key)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key in {}", key.display()))?;
+
+    let signing_key = any_supported_type(&private_key)
+        .map_err(|e| format!("Unsupported private key in {}: {}", key.display(), e))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn read_pem(path: &Path) -> Result<Vec<Item>, String> {
+    File::open(path)
+        .and_then(|file| rustls_pemfile::read_all(&mut BufReader::new(file)))
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+}
+
+/// Gives the current certificate to each handshake, a reload only affects the new connections
+pub struct CertResolver {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// Last modification of the files, to reload them when they change
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+impl CertResolver {
+    pub fn new(config: &TlsConfig) -> io::Result<Arc<Self>> {
+        let current = load(&config.cert, &config.key).map_err(io::Error::other)?;
+
+        Ok(Arc::new(Self {
+            cert: config.cert.to_owned(),
+            key: config.key.to_owned(),
+            current: RwLock::new(Arc::new(current)),
+            modified: Mutex::new(modified(&config.cert, &config.key)),
+        }))
+    }
+
+    /// Server config of the HTTPS listeners, `HttpServer::bind_rustls` adds the ALPN protocols
+    pub fn server_config(self: &Arc<Self>) -> ServerConfig {
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone())
+    }
+
+    /// Read the files again, the current certificate is kept if they are invalid
+    pub fn reload(&self) {
+        match load(&self.cert, &self.key) {
+            Ok(certified_key) => {
+                if let Ok(mut current) = self.current.write() {
+                    *current = Arc::new(certified_key);
+                    info!("Reloaded the TLS certificate {}", self.cert.display());
+                }
+            }
+            Err(e) => error!(
+                "Failed to reload the TLS certificate, keeping the current one: {}",
+                e
+            ),
+        }
+    }
+
+    /// Reload on each SIGHUP
+    pub async fn reload_on_hangup(self: Arc<Self>) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => return error!("Failed to listen to SIGHUP: {}", e),
+        };
+
+        while hangup.recv().await.is_some() {
+            self.reload();
+        }
+    }
+
+    /// Reload when the files are modified, checked every `interval` seconds
+    pub async fn reload_on_change(self: Arc<Self>, interval: u64) {
+        if interval == 0 {
+            return;
+        }
+
+        loop {
+            actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+
+            let current = modified(&self.cert, &self.key);
+            let changed = match self.modified.lock() {
+                Ok(mut modified) if *modified != current => {
+                    *modified = current;
+                    true
+                }
+                _ => false,
+            };
+
+            if changed {
+                self.reload();
+            }
+        }
+    }
+}
+
+/// Latest modification time of the two files
+fn modified(cert: &Path, key: &Path) -> Option<SystemTime> {
+    [cert, key]
+        .iter()
+        .filter_map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .max()
+}
@@ -6,7 +6,7 @@ pub fn check_password_strength(password: &str) -> Result<(), ValidationError> {
     let mut has_digit = false;
 
     if password.len() < 8 || password.len() > 16 {
-        return Err(ValidationError::new("密码长度必须在8到16个字符之间"));
+        return Err(ValidationError::new("password_length"));
     }
 
     for ch in password.chars() {
@@ -22,11 +22,11 @@ pub fn check_password_strength(password: &str) -> Result<(), ValidationError> {
     if has_upper && has_lower && has_digit {
         Ok(())
     } else if !has_upper {
-        return Err(ValidationError::new("密码至少包含一个大写字母"));
+        Err(ValidationError::new("password_uppercase"))
     } else if !has_lower {
-        return Err(ValidationError::new("密码至少包含一个小写字母"));
+        Err(ValidationError::new("password_lowercase"))
     } else if !has_digit {
-        return Err(ValidationError::new("密码至少包含一个数字"));
+        Err(ValidationError::new("password_digit"))
     } else {
         Ok(())
     }
@@ -0,0 +1,298 @@
+use actix_web::rt::time::sleep;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::{
+    errors::Error::{self, BadRequest, LettreAddressError},
+    models::{
+        outbox::{OutboxBody, OutboxMessage},
+        users::codes::{Code, CodeType},
+    },
+    state::State,
+};
+
+/// Seconds a claimed message stays hidden from other workers
+const LEASE: i64 = 300;
+
+/// Sends the emails queued in the outbox, one at a time
+pub struct OutboxWorker {
+    state: State,
+}
+
+impl OutboxWorker {
+    pub fn new(state: State) -> Self {
+        Self { state }
+    }
+
+    /// Poll the outbox forever, meant to be spawned on the runtime
+    pub async fn run(self) {
+        let poll_interval = Duration::from_secs(self.state.config.outbox_config.poll_interval);
+
+        loop {
+            match self.process_one().await {
+                // the queue may have more due messages
+                Ok(true) => continue,
+                Ok(false) => sleep(poll_interval).await,
+                Err(e) => {
+                    error!("Failed to process the outbox: {:?}", e);
+                    sleep(poll_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Claim and settle one due message, returns whether there was one
+    pub async fn process_one(&self) -> Result<bool, Error> {
+        let state = &self.state;
+
+        let message = match state.outbox.claim(LEASE).await? {
+            Some(message) => message,
+            None => return Ok(false),
+        };
+
+        // the code has been used, replaced or locked since the email was queued
+        if !self.is_code_active(&message).await? {
+            info!(
+                "Dropping email to {}, its code is no longer active",
+                message.to()
+            );
+            state.outbox.delete(&message).await?;
+
+            return Ok(true);
+        }
+
+        match self.deliver(&message).await {
+            Ok(()) => state.outbox.delete(&message).await?,
+            // an invalid address would fail on every attempt
+            Err(e)
+                if message.is_last_attempt(&state.config.outbox_config)
+                    || matches!(e, LettreAddressError(_)) =>
+            {
+                error!("Giving up on email to {}: {:?}", message.to(), e);
+                state.outbox.dead_letter(&message, e.to_string()).await?;
+
+                // free the email for a new code, the user never received this one
+                if let Some(code_id) = message.code_id() {
+                    if let Some(code) = state.codes.find_one_by_id(code_id).await? {
+                        state.codes.deactivate(&code).await?;
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Failed to send email to {}: {:?}", message.to(), e);
+                let next_attempt_at = message.next_attempt(&state.config.outbox_config);
+                state
+                    .outbox
+                    .retry(&message, next_attempt_at, e.to_string())
+                    .await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn is_code_active(&self, message: &OutboxMessage) -> Result<bool, Error> {
+        match message.code_id() {
+            Some(code_id) => Ok(self
+                .state
+                .codes
+                .find_one_by_id(code_id)
+                .await?
+                .is_some_and(|code| code.is_active())),
+            None => Ok(true),
+        }
+    }
+
+    async fn deliver(&self, message: &OutboxMessage) -> Result<(), Error> {
+        let email = &self.state.email;
+        let to = message.to().to_owned();
+
+        match message.body() {
+            OutboxBody::Code {
+                code_type,
+                code_expire,
+                code_id,
+            } => {
+                // the same plaintext on each attempt, only its hash is stored
+                let plaintext = Code::plaintext(*code_id, &self.state.config.code_secret);
+
+                email
+                    .send_code(
+                        to,
+                        message.locale(),
+                        (*code_type).into(),
+                        plaintext,
+                        *code_expire,
+                    )
+                    .await
+            }
+            OutboxBody::PasswordResetRequest => self.issue_password_reset_code(to).await,
+            OutboxBody::Notification {
+                subject,
+                header,
+                message: text,
+            } => {
+                email
+                    .send_notification(to, message.locale(), subject, header, text.to_owned())
+                    .await
+            }
+        }
+    }
+
+    /// Queue the code of a password reset request, nothing is sent if no user has the email
+    async fn issue_password_reset_code(&self, email: String) -> Result<(), Error> {
+        let state = &self.state;
+
+        let user = match state.users.find_one_by_email(email.to_owned()).await? {
+            Some(user) => user,
+            None => {
+                info!(
+                    "Dropping password reset request for {}, no user has this email",
+                    email
+                );
+                return Ok(());
+            }
+        };
+
+        match Code::issue(
+            email.to_owned(),
+            CodeType::PasswordReset,
+            None,
+            user.locale(),
+            &state.config,
+            state.codes.as_ref(),
+            state.outbox.as_ref(),
+        )
+        .await
+        {
+            // the pending code has been sent already, or is being sent
+            Err(BadRequest(_)) => {
+                info!(
+                    "Dropping password reset request for {}, a code is pending",
+                    email
+                );
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutboxWorker;
+    use crate::{
+        i18n::Locale,
+        models::{
+            outbox::{repository::OutboxRepository, OutboxBody, OutboxMessage},
+            users::{
+                codes::{repository::CodeRepository, Code, CodeType},
+                repository::UserRepository,
+                role::Role,
+                User,
+            },
+        },
+        testing::Fixture,
+    };
+
+    const EMAIL: &str = "alice@example.com";
+
+    async fn request_password_reset(fixture: &Fixture) -> OutboxWorker {
+        let message = OutboxMessage::password_reset_request(EMAIL.to_owned(), Locale::En);
+        fixture.outbox.enqueue(&message).await.unwrap();
+
+        let worker = OutboxWorker::new(fixture.state.clone());
+        assert!(worker.process_one().await.unwrap());
+
+        worker
+    }
+
+    #[actix_web::test]
+    async fn password_reset_request_without_user_sends_nothing() {
+        let fixture = Fixture::new();
+
+        let worker = request_password_reset(&fixture).await;
+
+        assert!(fixture.outbox.messages().unwrap().is_empty());
+        assert!(!worker.process_one().await.unwrap());
+        assert!(fixture.mailer.messages().await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn password_reset_request_queues_the_code_of_the_user() {
+        let fixture = Fixture::new();
+        let user = User::new(
+            EMAIL.to_owned(),
+            "alice".to_owned(),
+            "Passw0rd".to_owned(),
+            Role::User,
+        );
+        fixture.users.create(&user).await.unwrap();
+
+        let worker = request_password_reset(&fixture).await;
+
+        let messages = fixture.outbox.messages().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0].body(), OutboxBody::Code { .. }));
+
+        assert!(worker.process_one().await.unwrap());
+        assert_eq!(fixture.mailer.messages().await.len(), 1);
+
+        // a second request while the code is pending is dropped
+        request_password_reset(&fixture).await;
+        assert!(fixture.outbox.messages().unwrap().is_empty());
+    }
+
+    /// Queue the email of a new registration code sent to the address
+    async fn queue_code(fixture: &Fixture, to: &str) -> Code {
+        let config = &fixture.state.config;
+        let code = Code::new(
+            to.to_owned(),
+            None,
+            CodeType::Registration,
+            config.code_expire,
+            &config.code_secret,
+        );
+        fixture.codes.create(&code).await.unwrap();
+
+        let message = OutboxMessage::code(to.to_owned(), Locale::En, config.code_expire, &code);
+        fixture.outbox.enqueue(&message).await.unwrap();
+
+        code
+    }
+
+    #[actix_web::test]
+    async fn send_the_code_derived_from_its_id() {
+        let fixture = Fixture::new();
+        let code = queue_code(&fixture, EMAIL).await;
+
+        let worker = OutboxWorker::new(fixture.state.clone());
+        assert!(worker.process_one().await.unwrap());
+
+        let plaintext = Code::plaintext(code.id(), &fixture.state.config.code_secret);
+        let messages = fixture.mailer.messages().await;
+        assert!(messages[0].1.contains(&plaintext));
+
+        let stored = fixture.codes.find_one_by_id(code.id()).await.unwrap();
+        assert!(stored
+            .unwrap()
+            .is_valid(plaintext, &fixture.state.config.code_secret));
+    }
+
+    #[actix_web::test]
+    async fn dead_letter_invalid_addresses_at_once() {
+        let fixture = Fixture::new();
+        let code = queue_code(&fixture, "not an email").await;
+
+        let worker = OutboxWorker::new(fixture.state.clone());
+        assert!(worker.process_one().await.unwrap());
+
+        let messages = fixture.outbox.messages().unwrap();
+        let message = serde_json::to_value(&messages[0]).unwrap();
+        assert_eq!(message["status"], "deadLetter");
+
+        // the code is freed for a new one
+        let stored = fixture.codes.find_one_by_id(code.id()).await.unwrap();
+        assert!(!stored.unwrap().is_active());
+    }
+}